@@ -0,0 +1,431 @@
+//! Asynchronous retries.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::{future::Ready, time::Duration};
+//! # use retry::delay::Fixed;
+//! use retry::{retry_async, Error};
+//!
+//! # fn sleep(_: Duration) -> Ready<()> { std::future::ready(()) }
+//! # async fn fetch() -> Result<u32, &'static str> { Ok(1) }
+//! async fn fetch_with_retries() -> Result<u32, Error<&'static str>> {
+//!     // `sleep` is any function of a `Duration` returning a future, such as
+//!     // `tokio::time::sleep` or `async_std::task::sleep`.
+//!     retry_async(Fixed::from_millis(100).take(3), sleep, || fetch()).await
+//! }
+//! ```
+
+use std::{future::Future, time::Duration};
+
+use crate::{Clock, Error, ErrorKind, OperationResult, SystemClock};
+
+/// A source of asynchronous delays, used by [`retry_async`] and [`retry_async_with_index`] to wait
+/// between tries without blocking the current thread.
+///
+/// This is implemented for any function or closure that takes a [`Duration`] and returns a
+/// future, so the sleep function of any async runtime can be passed directly.
+pub trait AsyncSleeper {
+    /// The future returned by [`AsyncSleeper::sleep`].
+    type Sleep: Future<Output = ()>;
+
+    /// Return a future that completes after the given duration has elapsed.
+    fn sleep(&self, duration: Duration) -> Self::Sleep;
+}
+
+impl<F, S> AsyncSleeper for F
+where
+    F: Fn(Duration) -> S,
+    S: Future<Output = ()>,
+{
+    type Sleep = S;
+
+    fn sleep(&self, duration: Duration) -> S {
+        self(duration)
+    }
+}
+
+/// How the asynchronous retry functions handle a try that returns
+/// [`OperationResult::RetryAfter`], with the same controls as [`Retry::retry_after_consumes_delay`]
+/// and [`Retry::max_retry_after`].
+///
+/// [`Retry::retry_after_consumes_delay`]: crate::Retry::retry_after_consumes_delay
+/// [`Retry::max_retry_after`]: crate::Retry::max_retry_after
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::{future::Ready, time::Duration};
+/// # use retry::delay::Fixed;
+/// use retry::{retry_async_with_options, Error, OperationResult, RetryAfterOptions};
+///
+/// # fn sleep(_: Duration) -> Ready<()> { std::future::ready(()) }
+/// # async fn fetch() -> OperationResult<u32, &'static str> { OperationResult::Ok(1) }
+/// async fn fetch_with_retries() -> Result<u32, Error<&'static str>> {
+///     let options = RetryAfterOptions::new().max_retry_after(Duration::from_secs(5));
+///
+///     retry_async_with_options(Fixed::from_millis(100).take(3), sleep, options, || fetch()).await
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryAfterOptions {
+    consumes_delay: bool,
+    max_retry_after: Option<Duration>,
+}
+
+impl RetryAfterOptions {
+    /// Create the default options, under which a requested delay takes a delay from the
+    /// [`Duration`] iterator and is waited for in full.
+    #[must_use]
+    pub const fn new() -> Self {
+        RetryAfterOptions {
+            consumes_delay: true,
+            max_retry_after: None,
+        }
+    }
+
+    /// Set whether a try that returns [`OperationResult::RetryAfter`] still takes a delay from the
+    /// [`Duration`] iterator, which is then discarded in favor of the requested delay.
+    ///
+    /// This is `true` by default, so that the iterator limits the number of tries regardless of
+    /// how the delays are chosen.
+    #[must_use]
+    pub const fn consumes_delay(mut self, consumes_delay: bool) -> Self {
+        self.consumes_delay = consumes_delay;
+        self
+    }
+
+    /// Wait no longer than the given duration after a try that returns
+    /// [`OperationResult::RetryAfter`], even if it requests a longer delay.
+    #[must_use]
+    pub const fn max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = Some(max_retry_after);
+        self
+    }
+}
+
+impl Default for RetryAfterOptions {
+    fn default() -> Self {
+        RetryAfterOptions::new()
+    }
+}
+
+/// Retry the given asynchronous operation until it succeeds, or until the given [`Duration`]
+/// iterator ends, waiting between tries with the given [`AsyncSleeper`].
+pub async fn retry_async<I, S, O, F, R, E, OR>(
+    iterable: I,
+    sleeper: S,
+    mut operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    S: AsyncSleeper,
+    O: FnMut() -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    retry_async_with_index(iterable, sleeper, |_| operation()).await
+}
+
+/// Retry the given asynchronous operation until it succeeds, or until the given [`Duration`]
+/// iterator ends, waiting between tries with the given [`AsyncSleeper`] and with each iteration of
+/// the operation receiving the number of the attempt as an argument.
+///
+/// A try that returns [`OperationResult::RetryAfter`] waits for the requested delay in place of
+/// the next delay from the iterator. Use [`retry_async_with_options`] to limit the requested delay
+/// or leave the iterator untouched.
+pub async fn retry_async_with_index<I, S, O, F, R, E, OR>(
+    iterable: I,
+    sleeper: S,
+    operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    S: AsyncSleeper,
+    O: FnMut(u64) -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    retry_async_loop(
+        iterable,
+        SystemClock,
+        sleeper,
+        RetryAfterOptions::default(),
+        operation,
+    )
+    .await
+}
+
+/// Retry the given asynchronous operation until it succeeds, or until the given [`Duration`]
+/// iterator ends, waiting between tries with the given [`AsyncSleeper`] and handling requested
+/// delays according to the given [`RetryAfterOptions`].
+pub async fn retry_async_with_options<I, S, O, F, R, E, OR>(
+    iterable: I,
+    sleeper: S,
+    options: RetryAfterOptions,
+    mut operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    S: AsyncSleeper,
+    O: FnMut() -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    retry_async_loop(iterable, SystemClock, sleeper, options, |_| operation()).await
+}
+
+pub(crate) async fn retry_async_loop<I, C, S, O, F, R, E, OR>(
+    iterable: I,
+    clock: C,
+    sleeper: S,
+    options: RetryAfterOptions,
+    mut operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    C: Clock,
+    S: AsyncSleeper,
+    O: FnMut(u64) -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    let mut iterator = iterable.into_iter();
+    let start = clock.now();
+    let mut current_try = 1;
+    let mut total_delay = Duration::default();
+    let mut operation_time = Duration::default();
+
+    loop {
+        let started_at = clock.now();
+        let result = operation(current_try).await.into();
+        operation_time += clock.now().saturating_duration_since(started_at);
+
+        let (error, kind) = match result {
+            OperationResult::Ok(value) => return Ok(value),
+            OperationResult::Retry(error) => {
+                if let Some(delay) = iterator.next() {
+                    sleeper.sleep(delay).await;
+                    current_try += 1;
+                    total_delay = total_delay.saturating_add(delay);
+                    continue;
+                }
+
+                (error, ErrorKind::Exhausted)
+            }
+            OperationResult::RetryAfter(error, delay) => {
+                if !options.consumes_delay || iterator.next().is_some() {
+                    let delay = options.max_retry_after.map_or(delay, |max| delay.min(max));
+                    sleeper.sleep(delay).await;
+                    current_try += 1;
+                    total_delay = total_delay.saturating_add(delay);
+                    continue;
+                }
+
+                (error, ErrorKind::Exhausted)
+            }
+            OperationResult::Err(error) => (error, ErrorKind::Fatal),
+        };
+
+        return Err(Error {
+            error,
+            total_delay,
+            tries: current_try,
+            operation_time,
+            elapsed: clock.now().saturating_duration_since(start),
+            kind,
+            timeouts: 0,
+            attempts: Vec::new(),
+        });
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        cell::RefCell,
+        future::{ready, Future, Ready},
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread::{self, Thread},
+        time::Duration,
+    };
+
+    use super::{retry_async, retry_async_with_index, retry_async_with_options, RetryAfterOptions};
+    use crate::delay::{Fixed, NoDelay};
+    use crate::{ErrorKind, OperationResult};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut context = Context::from_waker(&waker);
+
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    pub(crate) fn no_sleep(_: Duration) -> Ready<()> {
+        ready(())
+    }
+
+    #[test]
+    fn succeeds_with_infinite_retries() {
+        let mut collection = vec![1, 2, 3, 4, 5].into_iter();
+
+        let value = block_on(retry_async(NoDelay, no_sleep, || {
+            ready(match collection.next() {
+                Some(n) if n == 5 => Ok(n),
+                Some(_) => Err("not 5"),
+                None => Err("not 5"),
+            })
+        }))
+        .unwrap();
+
+        assert_eq!(value, 5);
+    }
+
+    #[test]
+    fn fails_after_last_try() {
+        let mut collection = vec![1].into_iter();
+
+        let res = block_on(retry_async(NoDelay.take(1), no_sleep, || {
+            ready(match collection.next() {
+                Some(n) if n == 2 => Ok(n),
+                Some(_) => Err("not 2"),
+                None => Err("not 2"),
+            })
+        }));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, "not 2");
+        assert_eq!(err.tries, 2);
+        assert_eq!(err.total_delay, Duration::from_millis(0));
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+    }
+
+    #[test]
+    fn fatal_errors() {
+        let mut collection = vec![1].into_iter();
+
+        let res = block_on(retry_async(NoDelay.take(2), no_sleep, || {
+            ready(match collection.next() {
+                Some(n) if n == 2 => OperationResult::Ok(n),
+                Some(_) => OperationResult::Err("no retry"),
+                None => OperationResult::Err("not 2"),
+            })
+        }));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, "no retry");
+        assert_eq!(err.tries, 1);
+        assert_eq!(err.total_delay, Duration::from_millis(0));
+        assert_eq!(err.kind, ErrorKind::Fatal);
+    }
+
+    #[test]
+    fn sleeps_through_sleeper() {
+        let slept = RefCell::new(Vec::new());
+        let sleeper = |delay| {
+            slept.borrow_mut().push(delay);
+            ready(())
+        };
+
+        let res = block_on(retry_async(Fixed::from_millis(10).take(2), sleeper, || {
+            ready(Err::<(), _>("fail"))
+        }));
+
+        assert_eq!(res.unwrap_err().total_delay, Duration::from_millis(20));
+        assert_eq!(
+            slept.into_inner(),
+            vec![Duration::from_millis(10), Duration::from_millis(10)]
+        );
+    }
+
+    #[test]
+    fn waits_for_requested_delay() {
+        let slept = RefCell::new(Vec::new());
+        let sleeper = |delay| {
+            slept.borrow_mut().push(delay);
+            ready(())
+        };
+
+        let res = block_on(retry_async(Fixed::from_millis(10).take(2), sleeper, || {
+            ready(OperationResult::<(), _>::RetryAfter(
+                "busy",
+                Duration::from_secs(5),
+            ))
+        }));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.total_delay, Duration::from_secs(10));
+        assert_eq!(slept.into_inner(), vec![Duration::from_secs(5); 2]);
+    }
+
+    #[test]
+    fn requested_delay_with_options() {
+        let slept = RefCell::new(Vec::new());
+        let sleeper = |delay| {
+            slept.borrow_mut().push(delay);
+            ready(())
+        };
+        let mut tries = 0;
+
+        let options = RetryAfterOptions::new()
+            .consumes_delay(false)
+            .max_retry_after(Duration::from_secs(2));
+        let res = block_on(retry_async_with_options(
+            Fixed::from_millis(10).take(1),
+            sleeper,
+            options,
+            || {
+                tries += 1;
+                ready(if tries < 3 {
+                    OperationResult::<(), _>::RetryAfter("busy", Duration::from_secs(5))
+                } else {
+                    OperationResult::Retry("fail")
+                })
+            },
+        ));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 4);
+        assert_eq!(
+            slept.into_inner(),
+            vec![
+                Duration::from_secs(2),
+                Duration::from_secs(2),
+                Duration::from_millis(10)
+            ]
+        );
+    }
+
+    #[test]
+    fn succeeds_with_index() {
+        let mut collection = vec![1, 2, 3].into_iter();
+
+        let value = block_on(retry_async_with_index(NoDelay, no_sleep, |current_try| {
+            ready(match collection.next() {
+                Some(n) if n == current_try => Ok(n),
+                Some(_) => Err("not current_try"),
+                None => Err("not current_try"),
+            })
+        }))
+        .unwrap();
+
+        assert_eq!(value, 1);
+    }
+}
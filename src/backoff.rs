@@ -0,0 +1,159 @@
+//! Delay strategies that react to the outcome of each try.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! use retry::{AttemptContext, Backoff, Retry};
+//!
+//! struct SlowDownOnOverload;
+//!
+//! impl Backoff<&str> for SlowDownOnOverload {
+//!     fn next_delay(&mut self, context: &AttemptContext<'_, &str>) -> Option<Duration> {
+//!         if context.attempt >= 3 {
+//!             return None;
+//!         }
+//!
+//!         match *context.error {
+//!             "overloaded" => Some(Duration::from_millis(50)),
+//!             _ => Some(Duration::from_millis(1)),
+//!         }
+//!     }
+//! }
+//!
+//! let result = Retry::with_backoff(SlowDownOnOverload).call(|| Err::<(), _>("unavailable"));
+//!
+//! assert_eq!(result.unwrap_err().tries, 3);
+//! ```
+
+use std::time::Duration;
+
+/// What is known about a failed try when choosing the delay before the next one, passed to
+/// [`Backoff::next_delay`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct AttemptContext<'a, E> {
+    /// The number of the try that failed, starting at 1.
+    pub attempt: u64,
+    /// The error returned by the operation on the failed try.
+    pub error: &'a E,
+    /// How long the failed try took to run.
+    pub duration: Duration,
+    /// The time since the first try started, including the failed try.
+    pub elapsed: Duration,
+    /// The duration spent waiting between tries so far.
+    pub total_delay: Duration,
+}
+
+/// A strategy for choosing how long to wait before the next try, given what happened on the
+/// failed one.
+///
+/// This is implemented for every [`Duration`] iterator, which ignores the context and yields its
+/// next delay, so the strategies in the [`delay`](crate::delay) module can be used wherever a
+/// [`Backoff`] is expected.
+pub trait Backoff<E> {
+    /// Return the delay before the next try, or `None` to stop retrying.
+    fn next_delay(&mut self, context: &AttemptContext<'_, E>) -> Option<Duration>;
+}
+
+impl<E, I> Backoff<E> for I
+where
+    I: Iterator<Item = Duration>,
+{
+    fn next_delay(&mut self, _: &AttemptContext<'_, E>) -> Option<Duration> {
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, time::Duration};
+
+    use super::{AttemptContext, Backoff};
+    use crate::delay::Fixed;
+    use crate::{ManualClock, Retry};
+
+    type Seen = (u64, u32, Duration, Duration, Duration);
+
+    struct Recording<'a>(&'a RefCell<Vec<Seen>>);
+
+    impl Backoff<u32> for Recording<'_> {
+        fn next_delay(&mut self, context: &AttemptContext<'_, u32>) -> Option<Duration> {
+            self.0.borrow_mut().push((
+                context.attempt,
+                *context.error,
+                context.duration,
+                context.elapsed,
+                context.total_delay,
+            ));
+
+            if context.attempt < 3 {
+                Some(Duration::from_millis(u64::from(*context.error) * 100))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn iterators_ignore_context() {
+        let mut delays = Fixed::from_millis(10).take(1);
+        let context = AttemptContext {
+            attempt: 1,
+            error: &"fail",
+            duration: Duration::default(),
+            elapsed: Duration::default(),
+            total_delay: Duration::default(),
+        };
+
+        assert_eq!(delays.next_delay(&context), Some(Duration::from_millis(10)));
+        assert_eq!(delays.next_delay(&context), None);
+    }
+
+    #[test]
+    fn receives_attempt_context() {
+        let clock = ManualClock::new();
+        let contexts = RefCell::new(Vec::new());
+
+        let res = Retry::with_backoff(Recording(&contexts))
+            .clock(&clock)
+            .sleeper(&clock)
+            .call_with_index(|current_try| {
+                clock.advance(Duration::from_millis(10));
+                Err::<(), _>(current_try as u32 + 1)
+            });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 3);
+        assert_eq!(
+            clock.sleeps(),
+            vec![Duration::from_millis(200), Duration::from_millis(300)]
+        );
+        assert_eq!(
+            contexts.into_inner(),
+            vec![
+                (
+                    1,
+                    2,
+                    Duration::from_millis(10),
+                    Duration::from_millis(10),
+                    Duration::default()
+                ),
+                (
+                    2,
+                    3,
+                    Duration::from_millis(10),
+                    Duration::from_millis(220),
+                    Duration::from_millis(200)
+                ),
+                (
+                    3,
+                    4,
+                    Duration::from_millis(10),
+                    Duration::from_millis(530),
+                    Duration::from_millis(500)
+                ),
+            ]
+        );
+    }
+}
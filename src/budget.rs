@@ -0,0 +1,216 @@
+//! A retry budget shared between retry loops.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::sync::Arc;
+//! # use retry::delay::NoDelay;
+//! use retry::{ErrorKind, Retry, RetryBudget};
+//!
+//! let budget = Arc::new(RetryBudget::new(20).retry_cost(10));
+//!
+//! let result = Retry::new(NoDelay)
+//!     .budget(Arc::clone(&budget))
+//!     .call(|| Err::<(), _>("unavailable"));
+//!
+//! let error = result.unwrap_err();
+//! assert_eq!(error.kind, ErrorKind::BudgetExhausted);
+//! assert_eq!(error.tries, 3);
+//! assert_eq!(budget.stats().retries, 2);
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A token bucket that limits how many retries a group of retry loops may make, to keep a failing
+/// dependency from being overwhelmed by retries.
+///
+/// The bucket starts full. Each retry withdraws [`RetryBudget::retry_cost`] tokens, and each
+/// successful operation deposits [`RetryBudget::success_deposit`] tokens, up to the capacity. A
+/// retry loop that finds too few tokens left stops retrying, and returns the error from its last
+/// try with the kind [`ErrorKind::BudgetExhausted`](crate::ErrorKind::BudgetExhausted). First
+/// tries are never limited by the budget.
+///
+/// With the default cost of 10 tokens per retry and deposit of 1 token per success, a drained
+/// budget allows about one retry for every ten successful operations.
+///
+/// A budget is shared between retry loops by wrapping it in an [`Arc`](std::sync::Arc), and is
+/// used with [`Retry::budget`](crate::Retry::budget).
+#[derive(Debug)]
+pub struct RetryBudget {
+    capacity: u64,
+    retry_cost: u64,
+    success_deposit: u64,
+    tokens: AtomicU64,
+    successes: AtomicU64,
+    retries: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// A snapshot of the state of a [`RetryBudget`], returned by [`RetryBudget::stats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct RetryBudgetStats {
+    /// The number of tokens currently in the budget.
+    pub tokens: u64,
+    /// The number of successful operations that deposited tokens.
+    pub successes: u64,
+    /// The number of retries that withdrew tokens.
+    pub retries: u64,
+    /// The number of retries that were not made because the budget had too few tokens.
+    pub rejected: u64,
+}
+
+impl RetryBudget {
+    /// Create a new, full [`RetryBudget`] holding the given number of tokens.
+    #[must_use]
+    pub fn new(capacity: u64) -> Self {
+        RetryBudget {
+            capacity,
+            retry_cost: 10,
+            success_deposit: 1,
+            tokens: AtomicU64::new(capacity),
+            successes: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Set the number of tokens withdrawn by each retry. The default is 10.
+    #[must_use]
+    pub fn retry_cost(mut self, retry_cost: u64) -> Self {
+        self.retry_cost = retry_cost;
+        self
+    }
+
+    /// Set the number of tokens deposited by each successful operation. The default is 1.
+    #[must_use]
+    pub fn success_deposit(mut self, success_deposit: u64) -> Self {
+        self.success_deposit = success_deposit;
+        self
+    }
+
+    /// Return the current number of tokens and how many retries the budget has allowed and
+    /// rejected.
+    #[must_use]
+    pub fn stats(&self) -> RetryBudgetStats {
+        RetryBudgetStats {
+            tokens: self.tokens.load(Ordering::Relaxed),
+            successes: self.successes.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Withdraw the cost of a retry, returning `false` if there are too few tokens left.
+    pub(crate) fn try_withdraw(&self) -> bool {
+        let withdrawn = self
+            .tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tokens| {
+                tokens.checked_sub(self.retry_cost)
+            })
+            .is_ok();
+
+        if withdrawn {
+            self.retries.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+
+        withdrawn
+    }
+
+    /// Deposit the reward for a successful operation, up to the capacity.
+    pub(crate) fn deposit(&self) {
+        let _ = self
+            .tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tokens| {
+                Some(
+                    tokens
+                        .saturating_add(self.success_deposit)
+                        .min(self.capacity),
+                )
+            });
+        self.successes.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::RetryBudget;
+    use crate::delay::NoDelay;
+    use crate::{ErrorKind, Retry};
+
+    #[test]
+    fn withdraws_and_deposits_tokens() {
+        let budget = RetryBudget::new(15).retry_cost(5).success_deposit(2);
+
+        assert!(budget.try_withdraw());
+        assert!(budget.try_withdraw());
+        budget.deposit();
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+
+        let stats = budget.stats();
+        assert_eq!(stats.tokens, 2);
+        assert_eq!(stats.successes, 1);
+        assert_eq!(stats.retries, 3);
+        assert_eq!(stats.rejected, 1);
+    }
+
+    #[test]
+    fn deposits_up_to_capacity() {
+        let budget = RetryBudget::new(3);
+
+        budget.deposit();
+
+        assert_eq!(budget.stats().tokens, 3);
+    }
+
+    #[test]
+    fn stops_retrying_when_exhausted() {
+        let budget = Arc::new(RetryBudget::new(2).retry_cost(1));
+        let mut collection = vec![1, 2, 3, 4].into_iter();
+
+        let res = Retry::new(NoDelay)
+            .budget(Arc::clone(&budget))
+            .call(|| Err::<(), _>(collection.next().unwrap()));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, 3);
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.kind, ErrorKind::BudgetExhausted);
+
+        let value = Retry::new(NoDelay)
+            .budget(Arc::clone(&budget))
+            .call(|| Ok::<_, ()>(1))
+            .unwrap();
+        assert_eq!(value, 1);
+        assert_eq!(budget.stats().tokens, 1);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let budget = Arc::new(RetryBudget::new(10).retry_cost(1));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let budget = Arc::clone(&budget);
+                thread::spawn(move || {
+                    Retry::new(NoDelay)
+                        .budget(budget)
+                        .call(|| Err::<(), _>("fail"))
+                        .unwrap_err()
+                        .tries
+                })
+            })
+            .collect();
+
+        let tries: u64 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+
+        assert_eq!(tries, 14);
+        assert_eq!(budget.stats().retries, 10);
+        assert_eq!(budget.stats().rejected, 4);
+    }
+}
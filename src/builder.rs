@@ -0,0 +1,1132 @@
+//! A builder for configuring how an operation is retried.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use retry::delay::Exponential;
+//! use retry::Retry;
+//!
+//! let mut collection = vec![1, 2, 3].into_iter();
+//!
+//! let result = Retry::new(Exponential::from_millis(10))
+//!     .max_tries(5)
+//!     .deadline(Duration::from_secs(1))
+//!     .notify(|event| eprintln!("try {} failed: {}", event.attempt, event.error))
+//!     .call(|| match collection.next() {
+//!         Some(n) if n == 3 => Ok("n is 3!"),
+//!         Some(_) => Err("n must be 3!"),
+//!         None => Err("n was never 3!"),
+//!     });
+//!
+//! assert_eq!(result, Ok("n is 3!"));
+//! ```
+
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    fmt::{Debug, Error as FmtError, Formatter},
+    sync::Arc,
+    time::Duration,
+};
+
+#[cfg(feature = "metrics")]
+use crate::metrics::{CallMetrics, Recorder};
+use crate::timeout::try_with_timeout;
+use crate::trace::{self, LoopSpan};
+use crate::{
+    Attempt, AttemptContext, Backoff, CancellationToken, CircuitBreaker, CircuitError, Clock,
+    Error, ErrorKind, OperationResult, RetryBudget, Sleeper, Success, SystemClock, TimeoutError,
+};
+
+/// A circuit breaker guarding a retry loop, and the error to return if it rejects the first try.
+type Circuit<'a, E> = Option<(&'a CircuitBreaker, fn() -> E)>;
+
+/// A failed try that is about to be retried, passed to the hook given to [`Retry::notify`] or
+/// [`retry_with_notify`](crate::retry_with_notify).
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct RetryEvent<'a, E> {
+    /// The number of the try that failed, starting at 1.
+    pub attempt: u64,
+    /// The error returned by the operation on the failed try.
+    pub error: &'a E,
+    /// The delay that is about to be waited before the next try.
+    pub delay: Duration,
+    /// The duration spent waiting between tries so far, not including [`RetryEvent::delay`].
+    pub total_delay: Duration,
+}
+
+/// A hook called with a [`RetryEvent`] after each failed try that is about to be retried.
+///
+/// This is implemented for the closures accepted by [`Retry::notify`], and for `()`, which does
+/// nothing. It is not exported, so that the type of a closure's argument can always be inferred.
+pub trait Notify<E> {
+    /// Handle a failed try that is about to be retried.
+    fn notify(&mut self, event: &RetryEvent<'_, E>);
+}
+
+impl<E, F> Notify<E> for F
+where
+    F: FnMut(&RetryEvent<'_, E>),
+{
+    fn notify(&mut self, event: &RetryEvent<'_, E>) {
+        self(event);
+    }
+}
+
+impl<E> Notify<E> for () {
+    fn notify(&mut self, _: &RetryEvent<'_, E>) {}
+}
+
+/// A predicate that decides whether an error returned as [`OperationResult::Retry`] should be
+/// retried.
+///
+/// This is implemented for the closures accepted by [`Retry::when`], and for `()`, which retries
+/// every error. It is not exported, so that the type of a closure's argument can always be
+/// inferred.
+pub trait RetryIf<E> {
+    /// Returns `true` if the operation should be retried after the given error, or `false` if the
+    /// error should be returned immediately.
+    fn retry_if(&mut self, error: &E) -> bool;
+}
+
+impl<E, F> RetryIf<E> for F
+where
+    F: FnMut(&E) -> bool,
+{
+    fn retry_if(&mut self, error: &E) -> bool {
+        self(error)
+    }
+}
+
+impl<E> RetryIf<E> for () {
+    fn retry_if(&mut self, _: &E) -> bool {
+        true
+    }
+}
+
+/// A configurable policy for retrying an operation synchronously.
+///
+/// A [`Retry`] is created from a [`Duration`] iterator, which determines how long to wait after
+/// each unsuccessful try, just like the one passed to [`retry`](crate::retry), or from a
+/// [`Backoff`] with [`Retry::with_backoff`]. Further limits and hooks are added with the builder
+/// methods, and the operation is run with [`Retry::call`] or [`Retry::call_with_index`], or with
+/// [`Retry::call_with_circuit_breaker`] or [`Retry::call_with_timeout`] to also guard each try.
+pub struct Retry<I, C = SystemClock, S = SystemClock, N = (), W = ()> {
+    delays: I,
+    clock: C,
+    sleeper: S,
+    max_tries: Option<u64>,
+    deadline: Option<Duration>,
+    cancellation: Option<CancellationToken>,
+    budget: Option<Arc<RetryBudget>>,
+    recorded_attempts: Option<usize>,
+    retry_after_consumes_delay: bool,
+    max_retry_after: Option<Duration>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<(String, Arc<dyn Recorder>)>,
+    notify: N,
+    when: W,
+}
+
+impl<I> Retry<I>
+where
+    I: Iterator<Item = Duration>,
+{
+    /// Create a new [`Retry`] that waits between tries according to the given [`Duration`]
+    /// iterator, and gives up when it ends.
+    #[must_use]
+    pub fn new<T>(delays: T) -> Self
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        Retry {
+            delays: delays.into_iter(),
+            clock: SystemClock,
+            sleeper: SystemClock,
+            max_tries: None,
+            deadline: None,
+            cancellation: None,
+            budget: None,
+            recorded_attempts: None,
+            retry_after_consumes_delay: true,
+            max_retry_after: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            notify: (),
+            when: (),
+        }
+    }
+}
+
+impl<B> Retry<B> {
+    /// Create a new [`Retry`] that waits between tries according to the given [`Backoff`], and
+    /// gives up when it returns `None`.
+    ///
+    /// Unlike a [`Duration`] iterator, the [`Backoff`] is given an [`AttemptContext`] describing
+    /// the failed try, including its error and how long it took, so the delay before the next try
+    /// can depend on what went wrong.
+    #[must_use]
+    pub fn with_backoff(backoff: B) -> Self {
+        Retry {
+            delays: backoff,
+            clock: SystemClock,
+            sleeper: SystemClock,
+            max_tries: None,
+            deadline: None,
+            cancellation: None,
+            budget: None,
+            recorded_attempts: None,
+            retry_after_consumes_delay: true,
+            max_retry_after: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            notify: (),
+            when: (),
+        }
+    }
+}
+
+impl<I, C, S, N, W> Retry<I, C, S, N, W> {
+    /// Give up after the operation has been tried the given number of times, even if the
+    /// [`Duration`] iterator has not ended. The operation is always tried at least once.
+    #[must_use]
+    pub fn max_tries(mut self, max_tries: u64) -> Self {
+        self.max_tries = Some(max_tries);
+        self
+    }
+
+    /// Give up once the given duration has passed since the first try, or when the next delay
+    /// would end after it. See [`retry_with_deadline`](crate::retry_with_deadline).
+    #[must_use]
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Give up when the given [`CancellationToken`] is cancelled. Waits between tries go through
+    /// [`Sleeper::sleep_cancellable`], so with the default [`SystemClock`] a wait is interrupted as
+    /// soon as the token is cancelled. See
+    /// [`retry_with_cancellation`](crate::retry_with_cancellation).
+    #[must_use]
+    pub fn cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Only retry while the given shared [`RetryBudget`] has tokens left, withdrawing from it for
+    /// each retry and depositing into it when the operation succeeds. When too few tokens are
+    /// left for another retry, the error from the last try is returned with the kind
+    /// [`ErrorKind::BudgetExhausted`].
+    #[must_use]
+    pub fn budget(mut self, budget: Arc<RetryBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Keep a record of every try that is retried, available from [`Error::attempts`] if the
+    /// operation ultimately fails.
+    #[must_use]
+    pub fn record_attempts(mut self) -> Self {
+        self.recorded_attempts = Some(usize::MAX);
+        self
+    }
+
+    /// Keep a record of the most recent tries that are retried, up to the given number, available
+    /// from [`Error::attempts`] if the operation ultimately fails. Older tries are discarded, so
+    /// that retrying indefinitely does not hold on to an unbounded number of errors.
+    #[must_use]
+    pub fn record_attempts_with_capacity(mut self, capacity: usize) -> Self {
+        self.recorded_attempts = Some(capacity);
+        self
+    }
+
+    /// Set whether a try that returns [`OperationResult::RetryAfter`] still takes a delay from the
+    /// [`Duration`] iterator or [`Backoff`], which is then discarded in favor of the requested
+    /// delay.
+    ///
+    /// This is `true` by default, so that the iterator limits the number of tries regardless of
+    /// how the delays are chosen. When `false`, the iterator is left untouched and the next
+    /// [`OperationResult::Retry`] receives the delay it would have been given.
+    #[must_use]
+    pub fn retry_after_consumes_delay(mut self, consumes_delay: bool) -> Self {
+        self.retry_after_consumes_delay = consumes_delay;
+        self
+    }
+
+    /// Wait no longer than the given duration after a try that returns
+    /// [`OperationResult::RetryAfter`], even if it requests a longer delay.
+    #[must_use]
+    pub fn max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = Some(max_retry_after);
+        self
+    }
+
+    /// Report each finished call to the given [`Recorder`] under the given operation name. (When
+    /// the `metrics` Cargo feature is enabled.)
+    #[cfg(feature = "metrics")]
+    #[must_use]
+    pub fn metrics(mut self, operation: impl Into<String>, recorder: Arc<dyn Recorder>) -> Self {
+        self.metrics = Some((operation.into(), recorder));
+        self
+    }
+
+    /// Measure elapsed time with the given [`Clock`] instead of [`SystemClock`].
+    #[must_use]
+    pub fn clock<T>(self, clock: T) -> Retry<I, T, S, N, W>
+    where
+        T: Clock,
+    {
+        Retry {
+            delays: self.delays,
+            clock,
+            sleeper: self.sleeper,
+            max_tries: self.max_tries,
+            deadline: self.deadline,
+            cancellation: self.cancellation,
+            budget: self.budget,
+            recorded_attempts: self.recorded_attempts,
+            retry_after_consumes_delay: self.retry_after_consumes_delay,
+            max_retry_after: self.max_retry_after,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+            notify: self.notify,
+            when: self.when,
+        }
+    }
+
+    /// Wait between tries with the given [`Sleeper`] instead of [`SystemClock`].
+    ///
+    /// This has no effect when a [`CancellationToken`] is set, as waiting is then done by the
+    /// token so that it can be interrupted.
+    #[must_use]
+    pub fn sleeper<T>(self, sleeper: T) -> Retry<I, C, T, N, W>
+    where
+        T: Sleeper,
+    {
+        Retry {
+            delays: self.delays,
+            clock: self.clock,
+            sleeper,
+            max_tries: self.max_tries,
+            deadline: self.deadline,
+            cancellation: self.cancellation,
+            budget: self.budget,
+            recorded_attempts: self.recorded_attempts,
+            retry_after_consumes_delay: self.retry_after_consumes_delay,
+            max_retry_after: self.max_retry_after,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+            notify: self.notify,
+            when: self.when,
+        }
+    }
+
+    /// Call the given hook after each failed try that is about to be retried. See
+    /// [`retry_with_notify`](crate::retry_with_notify).
+    #[must_use]
+    pub fn notify<E, T>(self, notify: T) -> Retry<I, C, S, T, W>
+    where
+        T: FnMut(&RetryEvent<'_, E>),
+    {
+        Retry {
+            delays: self.delays,
+            clock: self.clock,
+            sleeper: self.sleeper,
+            max_tries: self.max_tries,
+            deadline: self.deadline,
+            cancellation: self.cancellation,
+            budget: self.budget,
+            recorded_attempts: self.recorded_attempts,
+            retry_after_consumes_delay: self.retry_after_consumes_delay,
+            max_retry_after: self.max_retry_after,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+            notify,
+            when: self.when,
+        }
+    }
+
+    /// Only retry errors returned as [`OperationResult::Retry`] for which the given predicate
+    /// returns `true`. Other errors are returned immediately, as if the operation had returned
+    /// [`OperationResult::Err`]. See [`retry_if`](crate::retry_if).
+    #[must_use]
+    pub fn when<E, T>(self, when: T) -> Retry<I, C, S, N, T>
+    where
+        T: FnMut(&E) -> bool,
+    {
+        Retry {
+            delays: self.delays,
+            clock: self.clock,
+            sleeper: self.sleeper,
+            max_tries: self.max_tries,
+            deadline: self.deadline,
+            cancellation: self.cancellation,
+            budget: self.budget,
+            recorded_attempts: self.recorded_attempts,
+            retry_after_consumes_delay: self.retry_after_consumes_delay,
+            max_retry_after: self.max_retry_after,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+            notify: self.notify,
+            when,
+        }
+    }
+
+    /// Retry the given operation until it succeeds or one of the configured limits is reached.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the last try, along with the reason no further tries were made.
+    pub fn call<O, R, E, OR>(self, mut operation: O) -> Result<R, Error<E>>
+    where
+        I: Backoff<E>,
+        C: Clock,
+        S: Sleeper,
+        N: Notify<E>,
+        W: RetryIf<E>,
+        O: FnMut() -> OR,
+        OR: Into<OperationResult<R, E>>,
+    {
+        self.call_with_index(|_| operation())
+    }
+
+    /// Retry the given operation until it succeeds or one of the configured limits is reached,
+    /// with each iteration of the operation receiving the number of the attempt as an argument.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the last try, along with the reason no further tries were made.
+    pub fn call_with_index<O, R, E, OR>(self, operation: O) -> Result<R, Error<E>>
+    where
+        I: Backoff<E>,
+        C: Clock,
+        S: Sleeper,
+        N: Notify<E>,
+        W: RetryIf<E>,
+        O: FnMut(u64) -> OR,
+        OR: Into<OperationResult<R, E>>,
+    {
+        self.run(None, operation).map(|success| success.value)
+    }
+
+    /// Retry the given operation until it succeeds or one of the configured limits is reached,
+    /// returning the successful value along with how many tries it took and how long they took.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the last try, along with the reason no further tries were made.
+    pub fn call_with_report<O, R, E, OR>(self, mut operation: O) -> Result<Success<R>, Error<E>>
+    where
+        I: Backoff<E>,
+        C: Clock,
+        S: Sleeper,
+        N: Notify<E>,
+        W: RetryIf<E>,
+        O: FnMut() -> OR,
+        OR: Into<OperationResult<R, E>>,
+    {
+        self.run(None, |_| operation())
+    }
+
+    /// Retry the given operation until it succeeds, until one of the configured limits is
+    /// reached, or until the given [`CircuitBreaker`] rejects a try. See
+    /// [`retry_with_circuit_breaker`](crate::retry_with_circuit_breaker) for how the circuit
+    /// breaker is consulted.
+    ///
+    /// Errors are wrapped in [`CircuitError::Operation`] before they reach the hooks and the
+    /// [`Backoff`], so these are given a [`CircuitError`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the last try, along with the reason no further tries were made, or
+    /// [`CircuitError::Open`] if the first try was rejected.
+    pub fn call_with_circuit_breaker<O, R, E, OR>(
+        self,
+        breaker: &CircuitBreaker,
+        mut operation: O,
+    ) -> Result<R, Error<CircuitError<E>>>
+    where
+        I: Backoff<CircuitError<E>>,
+        C: Clock,
+        S: Sleeper,
+        N: Notify<CircuitError<E>>,
+        W: RetryIf<CircuitError<E>>,
+        O: FnMut() -> OR,
+        OR: Into<OperationResult<R, E>>,
+    {
+        self.run(Some((breaker, || CircuitError::Open)), |_| {
+            operation().into().map_err(CircuitError::Operation)
+        })
+        .map(|success| success.value)
+    }
+
+    /// Retry the given operation until it succeeds or one of the configured limits is reached,
+    /// giving up on any try that does not finish within the given timeout. See
+    /// [`retry_with_timeout`](crate::retry_with_timeout) for how each try is run.
+    ///
+    /// Errors are wrapped in [`TimeoutError::Operation`] before they reach the hooks and the
+    /// [`Backoff`], so these are given a [`TimeoutError`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the last try, along with the reason no further tries were made.
+    ///
+    /// # Panics
+    ///
+    /// If the operation panics, the panic is propagated to the caller.
+    pub fn call_with_timeout<O, R, E, OR>(
+        self,
+        timeout: Duration,
+        operation: O,
+    ) -> Result<R, Error<TimeoutError<E>>>
+    where
+        I: Backoff<TimeoutError<E>>,
+        C: Clock,
+        S: Sleeper,
+        N: Notify<TimeoutError<E>>,
+        W: RetryIf<TimeoutError<E>>,
+        O: Fn() -> OR + Send + Sync + 'static,
+        OR: Into<OperationResult<R, E>>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        let operation = Arc::new(operation);
+        let timeouts = Cell::new(0);
+
+        self.run(None, |_| try_with_timeout(&operation, timeout, &timeouts))
+            .map(|success| success.value)
+            .map_err(|error| Error {
+                timeouts: timeouts.get(),
+                ..error
+            })
+    }
+
+    /// Run the retry loop. If a circuit breaker is given, a permit is asked for right before each
+    /// try, after any delay, and the outcome of the try is recorded with it. A rejected try is
+    /// not made, and the loop stops with the error of the last try that was made, or the given
+    /// error if it was the first try.
+    fn run<O, R, E, OR>(
+        mut self,
+        circuit: Circuit<'_, E>,
+        mut operation: O,
+    ) -> Result<Success<R>, Error<E>>
+    where
+        I: Backoff<E>,
+        C: Clock,
+        S: Sleeper,
+        N: Notify<E>,
+        W: RetryIf<E>,
+        O: FnMut(u64) -> OR,
+        OR: Into<OperationResult<R, E>>,
+    {
+        let start = self.clock.now();
+        let mut current_try = 0;
+        let mut total_delay = Duration::default();
+        let mut operation_time = Duration::default();
+        let mut attempts = VecDeque::new();
+        let mut retried: Option<Attempt<E>> = None;
+        let span = LoopSpan::enter();
+
+        let (error, kind) = loop {
+            let permit = match circuit {
+                Some((breaker, rejected)) => match breaker.try_acquire() {
+                    Some(permit) => Some(permit),
+                    None => {
+                        let error = retried.map_or_else(rejected, |attempt| attempt.error);
+                        break (error, ErrorKind::CircuitOpen);
+                    }
+                },
+                None => None,
+            };
+            if let Some(attempt) = retried.take() {
+                self.record(&mut attempts, attempt);
+            }
+            current_try += 1;
+
+            let started_at = self.clock.now();
+            let result = operation(current_try).into();
+            if let Some(permit) = permit {
+                permit.record(&result);
+            }
+            let duration = self.clock.now().saturating_duration_since(started_at);
+            operation_time += duration;
+            let outcome = trace::outcome(&result);
+
+            let (error, requested_delay, retryable) = match result {
+                OperationResult::Ok(value) => {
+                    if let Some(budget) = &self.budget {
+                        budget.deposit();
+                    }
+
+                    span.attempt(current_try, outcome, None, total_delay);
+                    span.finish(current_try, total_delay, None);
+
+                    let success = Success {
+                        value,
+                        tries: current_try,
+                        total_delay,
+                        operation_time,
+                        elapsed: self.clock.now().saturating_duration_since(start),
+                    };
+
+                    #[cfg(feature = "metrics")]
+                    self.report(&CallMetrics {
+                        tries: success.tries,
+                        total_delay: success.total_delay,
+                        operation_time: success.operation_time,
+                        elapsed: success.elapsed,
+                        error_kind: None,
+                    });
+
+                    return Ok(success);
+                }
+                OperationResult::Retry(error) => (error, None, true),
+                OperationResult::RetryAfter(error, delay) => (error, Some(delay), true),
+                OperationResult::Err(error) => (error, None, false),
+            };
+
+            let kind = if !retryable || !self.when.retry_if(&error) {
+                span.attempt(current_try, outcome, None, total_delay);
+                ErrorKind::Fatal
+            } else {
+                let context = AttemptContext {
+                    attempt: current_try,
+                    error: &error,
+                    duration,
+                    elapsed: self.clock.now().saturating_duration_since(start),
+                    total_delay,
+                };
+
+                match self.next_delay(&context, requested_delay) {
+                    Ok(delay) => {
+                        span.attempt(
+                            current_try,
+                            outcome,
+                            Some(delay),
+                            total_delay.saturating_add(delay),
+                        );
+                        self.notify.notify(&RetryEvent {
+                            attempt: current_try,
+                            error: &error,
+                            delay,
+                            total_delay,
+                        });
+
+                        match self.wait(delay) {
+                            Ok(()) => {
+                                retried = Some(Attempt {
+                                    error,
+                                    started_after: started_at.saturating_duration_since(start),
+                                    duration,
+                                    delay,
+                                });
+                                total_delay = total_delay.saturating_add(delay);
+                                continue;
+                            }
+                            Err(waited) => {
+                                total_delay = total_delay.saturating_add(waited);
+                                ErrorKind::Cancelled
+                            }
+                        }
+                    }
+                    Err(kind) => {
+                        span.attempt(current_try, outcome, None, total_delay);
+                        kind
+                    }
+                }
+            };
+
+            break (error, kind);
+        };
+
+        span.finish(current_try, total_delay, Some(kind));
+
+        let error = Error {
+            error,
+            total_delay,
+            tries: current_try,
+            operation_time,
+            elapsed: self.clock.now().saturating_duration_since(start),
+            kind,
+            timeouts: 0,
+            attempts: attempts.into(),
+        };
+
+        #[cfg(feature = "metrics")]
+        self.report(&CallMetrics {
+            tries: error.tries,
+            total_delay: error.total_delay,
+            operation_time: error.operation_time,
+            elapsed: error.elapsed,
+            error_kind: Some(error.kind),
+        });
+
+        Err(error)
+    }
+}
+
+impl<I, C, S, N, W> Retry<I, C, S, N, W>
+where
+    C: Clock,
+    S: Sleeper,
+{
+    /// Return the delay before the next try, or the reason to stop retrying.
+    fn next_delay<E>(
+        &mut self,
+        context: &AttemptContext<'_, E>,
+        requested_delay: Option<Duration>,
+    ) -> Result<Duration, ErrorKind>
+    where
+        I: Backoff<E>,
+    {
+        let elapsed = context.elapsed;
+        let current_try = context.attempt;
+
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(ErrorKind::Cancelled);
+        }
+
+        if self
+            .max_tries
+            .is_some_and(|max_tries| current_try >= max_tries)
+        {
+            return Err(ErrorKind::Exhausted);
+        }
+
+        if self.deadline.is_some_and(|deadline| elapsed >= deadline) {
+            return Err(ErrorKind::DeadlineExceeded);
+        }
+
+        let delay = match requested_delay {
+            Some(requested_delay) => {
+                if self.retry_after_consumes_delay && self.delays.next_delay(context).is_none() {
+                    return Err(ErrorKind::Exhausted);
+                }
+
+                self.max_retry_after
+                    .map_or(requested_delay, |max| requested_delay.min(max))
+            }
+            None => self
+                .delays
+                .next_delay(context)
+                .ok_or(ErrorKind::Exhausted)?,
+        };
+
+        if self
+            .deadline
+            .is_some_and(|deadline| elapsed.checked_add(delay).is_none_or(|end| end > deadline))
+        {
+            return Err(ErrorKind::DeadlineExceeded);
+        }
+
+        if self
+            .budget
+            .as_ref()
+            .is_some_and(|budget| !budget.try_withdraw())
+        {
+            return Err(ErrorKind::BudgetExhausted);
+        }
+
+        Ok(delay)
+    }
+
+    /// Report a finished call to the metrics recorder, if one is set.
+    #[cfg(feature = "metrics")]
+    fn report(&self, call: &CallMetrics) {
+        if let Some((operation, recorder)) = &self.metrics {
+            recorder.record(operation, call);
+        }
+    }
+
+    /// Add a try that is being retried to the record, if enabled.
+    fn record<E>(&self, attempts: &mut VecDeque<Attempt<E>>, attempt: Attempt<E>) {
+        if let Some(capacity) = self.recorded_attempts {
+            if capacity == 0 {
+                return;
+            }
+
+            if attempts.len() == capacity {
+                attempts.pop_front();
+            }
+
+            attempts.push_back(attempt);
+        }
+    }
+
+    /// Wait for the given delay, or return the time actually waited if cancelled first.
+    fn wait(&self, delay: Duration) -> Result<(), Duration> {
+        match &self.cancellation {
+            Some(token) => {
+                let before = self.clock.now();
+                if self.sleeper.sleep_cancellable(delay, token) {
+                    Err(self
+                        .clock
+                        .now()
+                        .saturating_duration_since(before)
+                        .min(delay))
+                } else {
+                    Ok(())
+                }
+            }
+            None => {
+                self.sleeper.sleep(delay);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<I, C, S, N, W> Debug for Retry<I, C, S, N, W>
+where
+    I: Debug,
+    C: Debug,
+    S: Debug,
+{
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+        formatter
+            .debug_struct("Retry")
+            .field("delays", &self.delays)
+            .field("clock", &self.clock)
+            .field("sleeper", &self.sleeper)
+            .field("max_tries", &self.max_tries)
+            .field("deadline", &self.deadline)
+            .field("cancellation", &self.cancellation)
+            .field("budget", &self.budget)
+            .field("recorded_attempts", &self.recorded_attempts)
+            .field(
+                "retry_after_consumes_delay",
+                &self.retry_after_consumes_delay,
+            )
+            .field("max_retry_after", &self.max_retry_after)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Retry;
+    use crate::delay::{Fixed, NoDelay};
+    use crate::{
+        CancellationToken, CircuitBreaker, CircuitError, ErrorKind, ManualClock, OperationResult,
+        Sleeper, TimeoutError,
+    };
+
+    #[test]
+    fn succeeds_with_defaults() {
+        let mut collection = vec![1, 2, 3].into_iter();
+
+        let value = Retry::new(NoDelay)
+            .call(|| match collection.next() {
+                Some(n) if n == 3 => Ok(n),
+                Some(_) => Err("not 3"),
+                None => Err("not 3"),
+            })
+            .unwrap();
+
+        assert_eq!(value, 3);
+    }
+
+    #[test]
+    fn stops_at_max_tries() {
+        let clock = ManualClock::new();
+
+        let res = Retry::new(Fixed::from_millis(10))
+            .max_tries(3)
+            .clock(&clock)
+            .sleeper(&clock)
+            .call_with_index(Err::<(), _>);
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, 3);
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+        assert_eq!(clock.sleeps(), vec![Duration::from_millis(10); 2]);
+    }
+
+    #[test]
+    fn max_tries_of_zero_tries_once() {
+        let res = Retry::new(NoDelay)
+            .max_tries(0)
+            .call(|| Err::<(), _>("fail"));
+
+        assert_eq!(res.unwrap_err().tries, 1);
+    }
+
+    #[test]
+    fn delays_end_before_max_tries() {
+        let res = Retry::new(NoDelay.take(1))
+            .max_tries(5)
+            .call(|| Err::<(), _>("fail"));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 2);
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+    }
+
+    #[test]
+    fn combines_options() {
+        let clock = ManualClock::new();
+        let mut attempts = Vec::new();
+
+        let res = Retry::new(Fixed::from_millis(100))
+            .max_tries(10)
+            .deadline(Duration::from_millis(250))
+            .clock(&clock)
+            .sleeper(&clock)
+            .notify(|event| attempts.push(event.attempt))
+            .call(|| OperationResult::<(), _>::Retry("fail"));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.elapsed, Duration::from_millis(200));
+        assert_eq!(err.kind, ErrorKind::DeadlineExceeded);
+        assert_eq!(attempts, vec![1, 2]);
+    }
+
+    #[test]
+    fn retries_only_when_predicate_matches() {
+        let mut collection = vec!["timeout", "timeout", "not found", "ok"].into_iter();
+
+        let res = Retry::new(NoDelay)
+            .when(|error: &&str| *error == "timeout")
+            .call(|| match collection.next() {
+                Some("ok") => Ok(()),
+                Some(error) => Err(error),
+                None => Err("empty"),
+            });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, "not found");
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.kind, ErrorKind::Fatal);
+    }
+
+    #[test]
+    fn records_attempts() {
+        let clock = ManualClock::new();
+
+        let res = Retry::new(Fixed::from_millis(100).take(2))
+            .record_attempts()
+            .clock(&clock)
+            .sleeper(&clock)
+            .call_with_index(|current_try| {
+                clock.advance(Duration::from_millis(10));
+                Err::<(), _>(current_try)
+            });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, 3);
+
+        let attempts = err.attempts();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].error, 1);
+        assert_eq!(attempts[0].started_after, Duration::ZERO);
+        assert_eq!(attempts[0].duration, Duration::from_millis(10));
+        assert_eq!(attempts[0].delay, Duration::from_millis(100));
+        assert_eq!(attempts[1].error, 2);
+        assert_eq!(attempts[1].started_after, Duration::from_millis(110));
+    }
+
+    #[test]
+    fn records_most_recent_attempts() {
+        let res = Retry::new(NoDelay.take(4))
+            .record_attempts_with_capacity(2)
+            .call_with_index(Err::<(), _>);
+
+        let err = res.unwrap_err();
+        let errors: Vec<_> = err.attempts().iter().map(|attempt| attempt.error).collect();
+        assert_eq!(errors, vec![3, 4]);
+    }
+
+    #[test]
+    fn does_not_record_attempts_by_default() {
+        let res = Retry::new(NoDelay.take(4)).call(|| Err::<(), _>("fail"));
+
+        assert!(res.unwrap_err().attempts().is_empty());
+    }
+
+    #[test]
+    fn reports_time_spent() {
+        let clock = ManualClock::new();
+        let mut collection = vec![1, 2, 3].into_iter();
+
+        let success = Retry::new(Fixed::from_millis(100))
+            .clock(&clock)
+            .sleeper(&clock)
+            .call_with_report(|| {
+                clock.advance(Duration::from_millis(10));
+                match collection.next() {
+                    Some(n) if n == 3 => Ok(n),
+                    _ => Err("not 3"),
+                }
+            })
+            .unwrap();
+
+        assert_eq!(success.value, 3);
+        assert_eq!(success.tries, 3);
+        assert_eq!(success.total_delay, Duration::from_millis(200));
+        assert_eq!(success.operation_time, Duration::from_millis(30));
+        assert_eq!(success.elapsed, Duration::from_millis(230));
+    }
+
+    #[test]
+    fn error_reports_time_spent() {
+        let clock = ManualClock::new();
+
+        let res = Retry::new(Fixed::from_millis(100).take(1))
+            .clock(&clock)
+            .sleeper(&clock)
+            .call(|| {
+                clock.advance(Duration::from_millis(10));
+                Err::<(), _>("fail")
+            });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.total_delay, Duration::from_millis(100));
+        assert_eq!(err.operation_time, Duration::from_millis(20));
+        assert_eq!(err.elapsed, Duration::from_millis(120));
+    }
+
+    #[test]
+    fn waits_for_requested_delay() {
+        let clock = ManualClock::new();
+
+        let res = Retry::new(Fixed::from_millis(10).take(2))
+            .clock(&clock)
+            .sleeper(&clock)
+            .call_with_index(|current_try| {
+                OperationResult::<(), _>::RetryAfter("throttled", Duration::from_secs(current_try))
+            });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+        assert_eq!(
+            clock.sleeps(),
+            vec![Duration::from_secs(1), Duration::from_secs(2)]
+        );
+    }
+
+    #[test]
+    fn requested_delay_without_consuming_delays() {
+        let clock = ManualClock::new();
+        let mut collection = vec![
+            OperationResult::RetryAfter("throttled", Duration::from_secs(5)),
+            OperationResult::Retry("unavailable"),
+            OperationResult::Ok(()),
+        ]
+        .into_iter();
+
+        let res = Retry::new(Fixed::from_millis(10).take(1))
+            .retry_after_consumes_delay(false)
+            .max_retry_after(Duration::from_secs(2))
+            .clock(&clock)
+            .sleeper(&clock)
+            .call(|| collection.next().unwrap());
+
+        assert!(res.is_ok());
+        assert_eq!(
+            clock.sleeps(),
+            vec![Duration::from_secs(2), Duration::from_millis(10)]
+        );
+    }
+
+    #[test]
+    fn huge_requested_delay_passes_deadline() {
+        let res = Retry::new(NoDelay)
+            .deadline(Duration::from_secs(1))
+            .call(|| OperationResult::<(), _>::RetryAfter("throttled", Duration::MAX));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DeadlineExceeded);
+        assert_eq!(err.tries, 1);
+    }
+
+    #[test]
+    fn cancellable_wait_uses_sleeper() {
+        let clock = ManualClock::new();
+        let token = CancellationToken::new();
+
+        let res = Retry::new(Fixed::from_millis(10_000).take(2))
+            .cancellation(token.clone())
+            .sleeper(&clock)
+            .call(|| Err::<(), _>("fail"));
+
+        assert_eq!(res.unwrap_err().kind, ErrorKind::Exhausted);
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(10); 2]);
+
+        struct CancellingSleeper(CancellationToken);
+
+        impl Sleeper for CancellingSleeper {
+            fn sleep(&self, _: Duration) {
+                self.0.cancel();
+            }
+        }
+
+        let res = Retry::new(Fixed::from_millis(10_000))
+            .cancellation(token.clone())
+            .sleeper(CancellingSleeper(token))
+            .call(|| Err::<(), _>("fail"));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Cancelled);
+        assert_eq!(err.tries, 1);
+    }
+
+    #[test]
+    fn combines_circuit_breaker_with_other_options() {
+        let breaker = CircuitBreaker::consecutive_failures(3, Duration::from_secs(60));
+        let mut notified = Vec::new();
+
+        let res = Retry::new(NoDelay)
+            .max_tries(2)
+            .notify(|event| notified.push(event.attempt))
+            .call_with_circuit_breaker(&breaker, || Err::<(), _>("unavailable"));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, CircuitError::Operation("unavailable"));
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+        assert_eq!(err.tries, 2);
+        assert_eq!(notified, vec![1]);
+
+        let res = Retry::new(NoDelay)
+            .record_attempts()
+            .call_with_circuit_breaker(&breaker, || Err::<(), _>("still unavailable"));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, CircuitError::Operation("still unavailable"));
+        assert_eq!(err.kind, ErrorKind::CircuitOpen);
+        assert_eq!(err.tries, 1);
+        assert!(err.attempts.is_empty());
+
+        let err = Retry::new(NoDelay)
+            .call_with_circuit_breaker(&breaker, || Ok::<_, ()>(()))
+            .unwrap_err();
+        assert_eq!(err.error, CircuitError::Open);
+        assert_eq!(err.tries, 0);
+    }
+
+    #[test]
+    fn combines_timeout_with_other_options() {
+        let res = Retry::new(NoDelay)
+            .max_tries(2)
+            .when(|error| matches!(error, TimeoutError::Elapsed(_)))
+            .call_with_timeout(Duration::from_millis(10), || {
+                std::thread::sleep(Duration::from_secs(1));
+                Ok::<_, ()>(())
+            });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, TimeoutError::Elapsed(Duration::from_millis(10)));
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+        assert_eq!(err.timeouts, 2);
+    }
+}
@@ -0,0 +1,105 @@
+//! Cancellation of synchronous retries.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::{thread, time::Duration};
+//! # use retry::delay::Fixed;
+//! use retry::{retry_with_cancellation, CancellationToken, ErrorKind};
+//!
+//! let token = CancellationToken::new();
+//! let shutdown = token.clone();
+//! thread::spawn(move || {
+//!     thread::sleep(Duration::from_millis(10));
+//!     shutdown.cancel();
+//! });
+//!
+//! let result = retry_with_cancellation(Fixed::from_millis(60_000), &token, || {
+//!     Err::<(), _>("unavailable")
+//! });
+//!
+//! assert_eq!(result.unwrap_err().kind, ErrorKind::Cancelled);
+//! ```
+
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+/// A handle that stops a retry loop started with [`retry_with_cancellation`].
+///
+/// Clones of a token share the same state, so one clone can be handed to the retry loop while
+/// another is cancelled from a different thread, such as a shutdown handler. Cancelling a token
+/// wakes a retry loop that is waiting between tries immediately.
+///
+/// [`retry_with_cancellation`]: crate::retry_with_cancellation
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl CancellationToken {
+    /// Create a new [`CancellationToken`] that has not been cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every retry loop using this token or one of its clones.
+    pub fn cancel(&self) {
+        *self.cancelled() = true;
+        self.inner.condvar.notify_all();
+    }
+
+    /// Returns `true` if [`CancellationToken::cancel`] has been called on this token or one of its
+    /// clones.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled()
+    }
+
+    /// Block until the given duration has elapsed or the token is cancelled, returning `true` if
+    /// the token was cancelled.
+    pub(crate) fn sleep(&self, duration: Duration) -> bool {
+        let (cancelled, _) = self
+            .inner
+            .condvar
+            .wait_timeout_while(self.cancelled(), duration, |cancelled| !*cancelled)
+            .unwrap_or_else(PoisonError::into_inner);
+
+        *cancelled
+    }
+
+    fn cancelled(&self) -> MutexGuard<'_, bool> {
+        self.inner
+            .cancelled
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[test]
+fn sleep_without_cancellation() {
+    let token = CancellationToken::new();
+
+    assert!(!token.sleep(Duration::from_millis(1)));
+    assert!(!token.is_cancelled());
+}
+
+#[test]
+fn cancel_wakes_sleep() {
+    let token = CancellationToken::new();
+    let clone = token.clone();
+    let handle = std::thread::spawn(move || clone.sleep(Duration::from_secs(60)));
+
+    token.cancel();
+
+    assert!(handle.join().unwrap());
+    assert!(token.is_cancelled());
+}
@@ -0,0 +1,749 @@
+//! A circuit breaker that stops retrying an operation that keeps failing.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use retry::delay::NoDelay;
+//! use retry::{retry_with_circuit_breaker, CircuitBreaker, CircuitError, CircuitState, ErrorKind};
+//!
+//! let breaker = CircuitBreaker::consecutive_failures(3, Duration::from_secs(30));
+//!
+//! let result = retry_with_circuit_breaker(NoDelay.take(10), &breaker, || {
+//!     Err::<(), _>("unavailable")
+//! });
+//!
+//! let error = result.unwrap_err();
+//! assert_eq!(error.error, CircuitError::Operation("unavailable"));
+//! assert_eq!(error.kind, ErrorKind::CircuitOpen);
+//! assert_eq!(error.tries, 3);
+//! assert_eq!(breaker.state(), CircuitState::Open);
+//! ```
+
+use std::{
+    collections::VecDeque,
+    error::Error as StdError,
+    fmt::{Display, Error as FmtError, Formatter},
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    asynchronous::retry_async_loop, AsyncSleeper, Error, ErrorKind, OperationResult, Retry,
+    RetryAfterOptions, SystemClock,
+};
+
+/// The state of a [`CircuitBreaker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CircuitState {
+    /// Tries are allowed, and their outcomes are counted towards the failure threshold.
+    Closed,
+    /// The failure threshold was reached, and tries are rejected until the cool-down has passed.
+    Open,
+    /// The cool-down has passed, and a single trial try is allowed to decide whether to close the
+    /// circuit again or to reopen it.
+    HalfOpen,
+}
+
+/// The error from a single try of an operation guarded by a [`CircuitBreaker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CircuitError<E> {
+    /// The circuit was open, so the operation was not tried.
+    Open,
+    /// The operation was tried and returned an error.
+    Operation(E),
+}
+
+impl<E> Display for CircuitError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            CircuitError::Open => formatter.write_str("circuit breaker is open"),
+            CircuitError::Operation(error) => Display::fmt(error, formatter),
+        }
+    }
+}
+
+impl<E> StdError for CircuitError<E>
+where
+    E: StdError + 'static,
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            CircuitError::Open => None,
+            CircuitError::Operation(error) => Some(error),
+        }
+    }
+}
+
+/// A thread-safe circuit breaker that makes retry loops fail fast while an operation keeps
+/// failing, used with [`retry_with_circuit_breaker`] and [`retry_async_with_circuit_breaker`].
+///
+/// The circuit starts closed. Once the failure threshold is reached it opens, and every try is
+/// rejected with [`CircuitError::Open`] until the cool-down has passed. The circuit is then half
+/// open, and a single trial try is let through: the circuit closes if it succeeds, or opens for
+/// another cool-down if it fails. If the trial try panics, or its future is dropped before it
+/// finishes, the next try becomes the trial instead.
+///
+/// Tries that return [`OperationResult::Retry`] or [`OperationResult::RetryAfter`] count as
+/// failures. Tries that return [`OperationResult::Ok`] or [`OperationResult::Err`] count as
+/// successes, since a fatal error shows that the operation's dependency is reachable.
+///
+/// Clones of a circuit breaker share the same state, so one breaker can guard every retry loop
+/// that calls the same dependency.
+///
+/// The constructors panic when given an invalid threshold, rather than adjusting it, since the
+/// threshold is normally fixed in code.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    threshold: Threshold,
+    cool_down: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+enum Threshold {
+    ConsecutiveFailures(u32),
+    FailureRate { rate: f64, window: usize },
+}
+
+#[derive(Debug)]
+struct State {
+    circuit: CircuitState,
+    opened_at: Option<Instant>,
+    consecutive_failures: u32,
+    outcomes: VecDeque<bool>,
+    trial_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    /// Create a new [`CircuitBreaker`] that opens after the given number of consecutive failed
+    /// tries, and stays open for the given cool-down.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is zero.
+    #[must_use]
+    pub fn consecutive_failures(threshold: u32, cool_down: Duration) -> Self {
+        assert!(threshold > 0, "failure threshold must not be zero");
+
+        Self::with_threshold(Threshold::ConsecutiveFailures(threshold), cool_down)
+    }
+
+    /// Create a new [`CircuitBreaker`] that opens once the given fraction of the most recent tries
+    /// have failed, and stays open for the given cool-down. The rate is only checked once `window`
+    /// tries have been made.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not greater than 0 and at most 1, or if `window` is zero.
+    #[must_use]
+    pub fn failure_rate(rate: f64, window: usize, cool_down: Duration) -> Self {
+        assert!(
+            rate > 0.0 && rate <= 1.0,
+            "failure rate must be greater than 0 and at most 1"
+        );
+        assert!(window > 0, "failure rate window must not be empty");
+
+        Self::with_threshold(Threshold::FailureRate { rate, window }, cool_down)
+    }
+
+    fn with_threshold(threshold: Threshold, cool_down: Duration) -> Self {
+        CircuitBreaker {
+            inner: Arc::new(Inner {
+                threshold,
+                cool_down,
+                state: Mutex::new(State {
+                    circuit: CircuitState::Closed,
+                    opened_at: None,
+                    consecutive_failures: 0,
+                    outcomes: VecDeque::new(),
+                    trial_in_flight: false,
+                }),
+            }),
+        }
+    }
+
+    /// Return the current state of the circuit. An open circuit whose cool-down has passed is
+    /// reported as [`CircuitState::HalfOpen`].
+    #[must_use]
+    pub fn state(&self) -> CircuitState {
+        let state = self.state_guard();
+
+        match state.circuit {
+            CircuitState::Open if self.cooled_down(&state, Instant::now()) => {
+                CircuitState::HalfOpen
+            }
+            circuit => circuit,
+        }
+    }
+
+    /// Close the circuit and forget all previous outcomes.
+    pub fn reset(&self) {
+        self.close(&mut self.state_guard());
+    }
+
+    /// Returns a [`Permit`] if a try may be made now, moving an open circuit whose cool-down has
+    /// passed to half open and claiming its trial try.
+    pub(crate) fn try_acquire(&self) -> Option<Permit<'_>> {
+        let mut state = self.state_guard();
+
+        let trial = match state.circuit {
+            CircuitState::Closed => false,
+            CircuitState::Open => {
+                if !self.cooled_down(&state, Instant::now()) {
+                    return None;
+                }
+
+                state.circuit = CircuitState::HalfOpen;
+                state.trial_in_flight = true;
+                true
+            }
+            CircuitState::HalfOpen => {
+                if std::mem::replace(&mut state.trial_in_flight, true) {
+                    return None;
+                }
+
+                true
+            }
+        };
+
+        Some(Permit {
+            breaker: self,
+            trial,
+        })
+    }
+
+    /// Record the outcome of a try that was allowed by [`CircuitBreaker::try_acquire`].
+    fn record<R, E>(&self, result: &OperationResult<R, E>) {
+        let failed = matches!(
+            result,
+            OperationResult::Retry(_) | OperationResult::RetryAfter(..)
+        );
+        let mut state = self.state_guard();
+
+        match state.circuit {
+            CircuitState::Closed => {
+                if failed {
+                    state.consecutive_failures += 1;
+                } else {
+                    state.consecutive_failures = 0;
+                }
+
+                if self.threshold_reached(&mut state, failed) {
+                    self.open(&mut state);
+                }
+            }
+            CircuitState::HalfOpen => {
+                state.trial_in_flight = false;
+
+                if failed {
+                    self.open(&mut state);
+                } else {
+                    self.close(&mut state);
+                }
+            }
+            CircuitState::Open => {}
+        }
+    }
+
+    fn threshold_reached(&self, state: &mut State, failed: bool) -> bool {
+        match self.inner.threshold {
+            Threshold::ConsecutiveFailures(threshold) => state.consecutive_failures >= threshold,
+            Threshold::FailureRate { rate, window } => {
+                if state.outcomes.len() == window {
+                    state.outcomes.pop_front();
+                }
+                state.outcomes.push_back(failed);
+
+                let failures = state.outcomes.iter().filter(|failed| **failed).count();
+                state.outcomes.len() == window && failures as f64 >= rate * window as f64
+            }
+        }
+    }
+
+    fn cooled_down(&self, state: &State, now: Instant) -> bool {
+        state.opened_at.is_none_or(|opened_at| {
+            now.saturating_duration_since(opened_at) >= self.inner.cool_down
+        })
+    }
+
+    fn open(&self, state: &mut State) {
+        state.circuit = CircuitState::Open;
+        state.opened_at = Some(Instant::now());
+    }
+
+    fn close(&self, state: &mut State) {
+        state.circuit = CircuitState::Closed;
+        state.opened_at = None;
+        state.consecutive_failures = 0;
+        state.outcomes.clear();
+        state.trial_in_flight = false;
+    }
+
+    fn state_guard(&self) -> MutexGuard<'_, State> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Permission from a [`CircuitBreaker`] to make a single try.
+///
+/// If the permit is dropped without recording an outcome, such as when the try panics or its
+/// future is dropped, a claimed half-open trial is given up so that another try can be the trial.
+#[derive(Debug)]
+pub(crate) struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    trial: bool,
+}
+
+impl Permit<'_> {
+    /// Record the outcome of the permitted try.
+    pub(crate) fn record<R, E>(mut self, result: &OperationResult<R, E>) {
+        self.breaker.record(result);
+        self.trial = false;
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if !self.trial {
+            return;
+        }
+
+        let mut state = self.breaker.state_guard();
+        if state.circuit == CircuitState::HalfOpen {
+            state.trial_in_flight = false;
+        }
+    }
+}
+
+/// The tries of a single asynchronous retry loop guarded by a [`CircuitBreaker`].
+///
+/// A permit is asked for right before each try, after any delay, so that a try is never made on
+/// the strength of a permit that the breaker has since withdrawn. The error of a try that is
+/// retried is kept aside, and the retry loop is given [`CircuitError::Open`] in its place. If the
+/// breaker then rejects the next try, the retry loop stops with that placeholder as a fatal error,
+/// and [`Tries::error`] puts the kept error back with the kind [`ErrorKind::CircuitOpen`].
+struct Tries<'a, E> {
+    breaker: &'a CircuitBreaker,
+    retried: Mutex<Option<E>>,
+}
+
+impl<'a, E> Tries<'a, E> {
+    fn new(breaker: &'a CircuitBreaker) -> Self {
+        Tries {
+            breaker,
+            retried: Mutex::new(None),
+        }
+    }
+
+    /// Ask the breaker for a permit to make the next try now.
+    fn acquire(&self) -> Option<Permit<'a>> {
+        self.breaker.try_acquire()
+    }
+
+    /// Record the outcome of a permitted try, keeping its error aside if it is to be retried.
+    fn finish<R>(
+        &self,
+        permit: Permit<'a>,
+        result: OperationResult<R, E>,
+    ) -> OperationResult<R, CircuitError<E>> {
+        permit.record(&result);
+
+        match result {
+            OperationResult::Ok(value) => OperationResult::Ok(value),
+            OperationResult::Retry(error) => {
+                self.keep(error);
+                OperationResult::Retry(CircuitError::Open)
+            }
+            OperationResult::RetryAfter(error, delay) => {
+                self.keep(error);
+                OperationResult::RetryAfter(CircuitError::Open, delay)
+            }
+            OperationResult::Err(error) => OperationResult::Err(CircuitError::Operation(error)),
+        }
+    }
+
+    fn keep(&self, error: E) {
+        *self.retried.lock().unwrap_or_else(PoisonError::into_inner) = Some(error);
+    }
+
+    /// Put the error of the last try that was made back into the error of the retry loop, giving
+    /// it the kind [`ErrorKind::CircuitOpen`] if the breaker stopped the loop.
+    fn error(&self, error: Error<CircuitError<E>>) -> Error<CircuitError<E>> {
+        if !matches!(error.error, CircuitError::Open) {
+            return error;
+        }
+
+        let retried = self
+            .retried
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+
+        match retried {
+            // The first try was rejected.
+            None => Error {
+                kind: ErrorKind::CircuitOpen,
+                tries: 0,
+                ..error
+            },
+            // A later try was rejected, which the retry loop counted as a try.
+            Some(retried) if error.kind == ErrorKind::Fatal => Error {
+                error: CircuitError::Operation(retried),
+                kind: ErrorKind::CircuitOpen,
+                tries: error.tries - 1,
+                ..error
+            },
+            // The retry loop gave up after the last try for another reason.
+            Some(retried) => Error {
+                error: CircuitError::Operation(retried),
+                ..error
+            },
+        }
+    }
+}
+
+/// Retry the given operation synchronously until it succeeds, until the given [`Duration`]
+/// iterator ends, or until the given [`CircuitBreaker`] rejects a try.
+///
+/// The outcome of each try is recorded by the circuit breaker, and each try is checked against it
+/// right before it is made, after any delay. A try that the breaker rejects is not made: retrying
+/// stops, and the returned [`Error`] has the kind [`ErrorKind::CircuitOpen`]. Its error is
+/// the one from the last try that was made, or [`CircuitError::Open`] if the first try was
+/// rejected, in which case [`Error::tries`] is zero.
+///
+/// To combine a circuit breaker with other limits or hooks, use
+/// [`Retry::call_with_circuit_breaker`].
+pub fn retry_with_circuit_breaker<I, O, R, E, OR>(
+    iterable: I,
+    breaker: &CircuitBreaker,
+    operation: O,
+) -> Result<R, Error<CircuitError<E>>>
+where
+    I: IntoIterator<Item = Duration>,
+    O: FnMut() -> OR,
+    OR: Into<OperationResult<R, E>>,
+{
+    Retry::new(iterable).call_with_circuit_breaker(breaker, operation)
+}
+
+/// Retry the given asynchronous operation until it succeeds, until the given [`Duration`]
+/// iterator ends, or until the given [`CircuitBreaker`] rejects a try, waiting between tries with
+/// the given [`AsyncSleeper`].
+///
+/// See [`retry_with_circuit_breaker`] for how the circuit breaker is consulted.
+pub async fn retry_async_with_circuit_breaker<I, S, O, F, R, E, OR>(
+    iterable: I,
+    sleeper: S,
+    breaker: &CircuitBreaker,
+    mut operation: O,
+) -> Result<R, Error<CircuitError<E>>>
+where
+    I: IntoIterator<Item = Duration>,
+    S: AsyncSleeper,
+    O: FnMut() -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    let tries = &Tries::new(breaker);
+
+    retry_async_loop(
+        iterable,
+        SystemClock,
+        sleeper,
+        RetryAfterOptions::default(),
+        |_| {
+            let attempt = tries.acquire().map(|permit| (permit, operation()));
+
+            async move {
+                match attempt {
+                    Some((permit, attempt)) => tries.finish(permit, attempt.await.into()),
+                    None => OperationResult::Err(CircuitError::Open),
+                }
+            }
+        },
+    )
+    .await
+    .map_err(|error| tries.error(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        future::{pending, ready, Future},
+        panic::{self, AssertUnwindSafe},
+        sync::Arc,
+        task::{Context, Wake},
+        thread,
+        time::Duration,
+    };
+
+    use super::{
+        retry_async_with_circuit_breaker, retry_with_circuit_breaker, CircuitBreaker, CircuitError,
+        CircuitState,
+    };
+    use crate::asynchronous::tests::{block_on, no_sleep};
+    use crate::delay::NoDelay;
+    use crate::{ErrorKind, OperationResult};
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::consecutive_failures(3, Duration::from_secs(60));
+        let mut calls = 0;
+
+        let res = retry_with_circuit_breaker(NoDelay.take(10), &breaker, || {
+            calls += 1;
+            Err::<(), _>("unavailable")
+        });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, CircuitError::Operation("unavailable"));
+        assert_eq!(err.kind, ErrorKind::CircuitOpen);
+        assert_eq!(err.tries, 3);
+        assert_eq!(calls, 3);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        let res = retry_with_circuit_breaker(NoDelay.take(10), &breaker.clone(), || {
+            calls += 1;
+            Ok::<_, &str>(())
+        });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, CircuitError::Open);
+        assert_eq!(err.kind, ErrorKind::CircuitOpen);
+        assert_eq!(err.tries, 0);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn successes_reset_consecutive_failures() {
+        let breaker = CircuitBreaker::consecutive_failures(2, Duration::from_secs(60));
+        let mut collection = vec![Err("a"), Ok(()), Err("b"), Ok(())].into_iter();
+
+        for _ in 0..2 {
+            let res = retry_with_circuit_breaker(NoDelay, &breaker, || collection.next().unwrap());
+            assert!(res.is_ok());
+        }
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn opens_at_failure_rate() {
+        let breaker = CircuitBreaker::failure_rate(0.5, 4, Duration::from_secs(60));
+        let mut collection = vec![
+            OperationResult::<(), _>::Retry("a"),
+            OperationResult::Err("fatal"),
+            OperationResult::Retry("b"),
+            OperationResult::Retry("c"),
+        ]
+        .into_iter();
+
+        let res = retry_with_circuit_breaker(NoDelay, &breaker, || collection.next().unwrap())
+            .unwrap_err();
+        assert_eq!(res.error, CircuitError::Operation("fatal"));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        let res = retry_with_circuit_breaker(NoDelay, &breaker, || collection.next().unwrap())
+            .unwrap_err();
+        assert_eq!(res.error, CircuitError::Operation("c"));
+        assert_eq!(res.kind, ErrorKind::CircuitOpen);
+        assert_eq!(res.tries, 2);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    #[should_panic(expected = "failure rate must be greater than 0")]
+    fn rejects_zero_failure_rate() {
+        let _ = CircuitBreaker::failure_rate(0.0, 2, Duration::from_secs(60));
+    }
+
+    #[test]
+    #[should_panic(expected = "failure threshold must not be zero")]
+    fn rejects_zero_consecutive_failures() {
+        let _ = CircuitBreaker::consecutive_failures(0, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn half_open_trial_closes_or_reopens() {
+        let breaker = CircuitBreaker::consecutive_failures(1, Duration::from_millis(50));
+
+        let res = retry_with_circuit_breaker(NoDelay.take(1), &breaker, || Err::<(), _>("down"));
+        assert_eq!(res.unwrap_err().kind, ErrorKind::CircuitOpen);
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        let res = retry_with_circuit_breaker(NoDelay, &breaker, || Err::<(), _>("still down"));
+        let err = res.unwrap_err();
+        assert_eq!(err.error, CircuitError::Operation("still down"));
+        assert_eq!(err.tries, 1);
+        assert_eq!(
+            breaker.inner.state.lock().unwrap().circuit,
+            CircuitState::Open
+        );
+
+        thread::sleep(Duration::from_millis(100));
+        let res = retry_with_circuit_breaker(NoDelay, &breaker, || Ok::<_, ()>(1));
+        assert_eq!(res.unwrap(), 1);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn half_open_allows_one_trial() {
+        let breaker = CircuitBreaker::consecutive_failures(1, Duration::ZERO);
+        let _ = retry_with_circuit_breaker(NoDelay.take(0), &breaker, || Err::<(), _>("down"));
+
+        let trial = breaker.try_acquire();
+        assert!(trial.is_some());
+        assert!(breaker.try_acquire().is_none());
+
+        drop(trial);
+        assert!(breaker.try_acquire().is_some());
+
+        breaker.reset();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire().is_some());
+    }
+
+    #[test]
+    fn panicking_trial_frees_slot() {
+        let breaker = CircuitBreaker::consecutive_failures(1, Duration::ZERO);
+        let _ = retry_with_circuit_breaker(NoDelay.take(0), &breaker, || Err::<(), _>("down"));
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            retry_with_circuit_breaker(NoDelay, &breaker, || -> Result<(), ()> {
+                panic!("trial panicked")
+            })
+        }));
+        assert!(res.is_err());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        let res = retry_with_circuit_breaker(NoDelay, &breaker, || Ok::<_, ()>(1));
+        assert_eq!(res.unwrap(), 1);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn dropped_trial_frees_slot() {
+        let breaker = CircuitBreaker::consecutive_failures(1, Duration::ZERO);
+        let _ = retry_with_circuit_breaker(NoDelay.take(0), &breaker, || Err::<(), _>("down"));
+
+        let mut trial = Box::pin(retry_async_with_circuit_breaker(
+            NoDelay,
+            no_sleep,
+            &breaker,
+            pending::<Result<(), ()>>,
+        ));
+        let waker = Arc::new(NoopWaker).into();
+        assert!(trial
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        assert!(breaker.try_acquire().is_none());
+
+        drop(trial);
+
+        let res = block_on(retry_async_with_circuit_breaker(
+            NoDelay,
+            no_sleep,
+            &breaker,
+            || ready(Ok::<_, ()>(1)),
+        ));
+        assert_eq!(res.unwrap(), 1);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn async_fails_fast() {
+        let breaker = CircuitBreaker::consecutive_failures(2, Duration::from_secs(60));
+
+        let res = block_on(retry_async_with_circuit_breaker(
+            NoDelay.take(5),
+            no_sleep,
+            &breaker,
+            || ready(Err::<(), _>("unavailable")),
+        ));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, CircuitError::Operation("unavailable"));
+        assert_eq!(err.kind, ErrorKind::CircuitOpen);
+        assert_eq!(err.tries, 2);
+    }
+
+    #[test]
+    fn checks_breaker_after_waiting() {
+        let breaker = CircuitBreaker::consecutive_failures(2, Duration::from_secs(60));
+        let mut calls = 0;
+
+        // Another loop opens the circuit while this one waits for its second try.
+        let sleeper = |_| {
+            let _ = retry_with_circuit_breaker(NoDelay.take(1), &breaker, || Err::<(), _>("b"));
+            ready(())
+        };
+        let res = block_on(retry_async_with_circuit_breaker(
+            NoDelay.take(2),
+            sleeper,
+            &breaker,
+            || {
+                calls += 1;
+                ready(Err::<(), _>("a"))
+            },
+        ));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, CircuitError::Operation("a"));
+        assert_eq!(err.kind, ErrorKind::CircuitOpen);
+        assert_eq!(err.tries, 1);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn does_not_hold_trial_while_waiting() {
+        let breaker = CircuitBreaker::consecutive_failures(1, Duration::ZERO);
+        let _ = retry_with_circuit_breaker(NoDelay.take(0), &breaker, || Err::<(), _>("down"));
+        let others = RefCell::new(Vec::new());
+
+        // The failed trial reopens the circuit, and another loop makes the next trial while this
+        // one waits.
+        let sleeper = |_| {
+            others.borrow_mut().push(retry_with_circuit_breaker(
+                NoDelay.take(0),
+                &breaker,
+                || Ok::<_, &str>(()),
+            ));
+            ready(())
+        };
+        let res = block_on(retry_async_with_circuit_breaker(
+            NoDelay.take(1),
+            sleeper,
+            &breaker,
+            || ready(Err::<(), _>("a")),
+        ));
+
+        assert_eq!(others.into_inner(), vec![Ok(())]);
+
+        let err = res.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+        assert_eq!(err.tries, 2);
+    }
+}
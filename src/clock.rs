@@ -0,0 +1,174 @@
+//! Sources of time and delay for synchronous retries.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use retry::delay::Fixed;
+//! use retry::{retry_with_sleeper, ManualClock};
+//!
+//! let clock = ManualClock::new();
+//! let result = retry_with_sleeper(Fixed::from_millis(1_000).take(2), &clock, || {
+//!     Err::<(), _>("fail")
+//! });
+//!
+//! assert!(result.is_err());
+//! assert_eq!(clock.sleeps(), vec![Duration::from_secs(1), Duration::from_secs(1)]);
+//! ```
+
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::CancellationToken;
+
+/// Waits between tries of a synchronous retry loop.
+pub trait Sleeper {
+    /// Block until the given duration has elapsed.
+    fn sleep(&self, duration: Duration);
+
+    /// Block until the given duration has elapsed or the given token is cancelled, returning
+    /// `true` if the token was cancelled.
+    ///
+    /// The default implementation sleeps for the whole duration with [`Sleeper::sleep`], then
+    /// checks the token. [`SystemClock`] wakes as soon as the token is cancelled instead.
+    fn sleep_cancellable(&self, duration: Duration, token: &CancellationToken) -> bool {
+        self.sleep(duration);
+        token.is_cancelled()
+    }
+}
+
+/// Reports the current time to a synchronous retry loop.
+pub trait Clock {
+    /// Return the current time.
+    fn now(&self) -> Instant;
+}
+
+impl<T> Sleeper for &T
+where
+    T: Sleeper + ?Sized,
+{
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration);
+    }
+
+    fn sleep_cancellable(&self, duration: Duration, token: &CancellationToken) -> bool {
+        (**self).sleep_cancellable(duration, token)
+    }
+}
+
+impl<T> Clock for &T
+where
+    T: Clock + ?Sized,
+{
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+/// The standard [`Sleeper`] and [`Clock`], which blocks the current thread with
+/// [`std::thread::sleep`] and reads the time from [`Instant::now`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Sleeper for SystemClock {
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    fn sleep_cancellable(&self, duration: Duration, token: &CancellationToken) -> bool {
+        token.sleep(duration)
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A [`Sleeper`] and [`Clock`] that never blocks. Sleeping records the requested delay and
+/// advances the clock by that amount instead, which makes retry loops fast and deterministic in
+/// tests.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    state: Mutex<ManualClockState>,
+}
+
+#[derive(Debug, Default)]
+struct ManualClockState {
+    elapsed: Duration,
+    sleeps: Vec<Duration>,
+}
+
+impl ManualClock {
+    /// Create a new [`ManualClock`] starting at the current time.
+    #[must_use]
+    pub fn new() -> Self {
+        ManualClock {
+            start: Instant::now(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Move the clock forward by the given duration without recording a sleep.
+    pub fn advance(&self, duration: Duration) {
+        self.state().elapsed += duration;
+    }
+
+    /// Return the total time the clock has moved forward since it was created.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.state().elapsed
+    }
+
+    /// Return every delay requested through [`Sleeper::sleep`], in order.
+    #[must_use]
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state().sleeps.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, ManualClockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sleeper for ManualClock {
+    fn sleep(&self, duration: Duration) {
+        let mut state = self.state();
+        state.elapsed += duration;
+        state.sleeps.push(duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.state().elapsed
+    }
+}
+
+#[test]
+fn manual_clock_records_sleeps() {
+    let clock = ManualClock::new();
+    let start = clock.now();
+
+    clock.sleep(Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    clock.sleep(Duration::from_secs(3));
+
+    assert_eq!(
+        clock.sleeps(),
+        vec![Duration::from_secs(1), Duration::from_secs(3)]
+    );
+    assert_eq!(clock.elapsed(), Duration::from_secs(6));
+    assert_eq!(clock.now() - start, Duration::from_secs(6));
+}
@@ -0,0 +1,302 @@
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "random")]
+use super::Jitter;
+
+/// Adapters for shaping any [`Duration`] iterator, such as one of the strategies in this module.
+///
+/// Each adapter is a named type, so that a shaped strategy can be stored in a struct field, and
+/// implements [`Debug`] and [`Clone`] whenever the underlying iterator does.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use retry::delay::{DelayExt, Exponential};
+///
+/// let delays: Vec<_> = Exponential::from_millis(100)
+///     .max_delay(Duration::from_millis(400))
+///     .max_total(Duration::from_secs(1))
+///     .collect();
+///
+/// assert_eq!(delays, [100, 200, 400].map(Duration::from_millis));
+/// ```
+pub trait DelayExt: Iterator<Item = Duration> + Sized {
+    /// Shorten every delay longer than the given cap to it.
+    fn max_delay(self, cap: Duration) -> MaxDelay<Self> {
+        MaxDelay { delays: self, cap }
+    }
+
+    /// Lengthen every delay shorter than the given floor to it.
+    fn min_delay(self, floor: Duration) -> MinDelay<Self> {
+        MinDelay {
+            delays: self,
+            floor,
+        }
+    }
+
+    /// Stop when the next delay would take the sum of all delays past the given budget.
+    fn max_total(self, budget: Duration) -> MaxTotal<Self> {
+        MaxTotal {
+            delays: self,
+            budget,
+            total: Duration::default(),
+        }
+    }
+
+    /// Apply full random jitter to every delay, as with [`jitter`](super::jitter). (When the
+    /// `random` Cargo feature is enabled.)
+    #[cfg(feature = "random")]
+    fn jitter(self) -> Jitter<Self> {
+        Jitter::new(self)
+    }
+
+    /// Multiply every delay by the given factor, saturating at [`Duration::MAX`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the factor is negative or not a number.
+    fn scale(self, factor: f64) -> Scale<Self> {
+        assert!(factor >= 0.0, "factor must not be negative");
+
+        Scale {
+            delays: self,
+            factor,
+        }
+    }
+
+    /// Stop when the next delay would end more than the given duration after the first delay
+    /// was taken, measured with the system clock. The clock starts on the first call to
+    /// [`next`](Iterator::next), so the adapter can be created, or cloned, ahead of time.
+    fn take_for(self, limit: Duration) -> TakeFor<Self> {
+        TakeFor {
+            delays: self,
+            limit,
+            start: None,
+        }
+    }
+
+    /// Keep repeating the last delay once the iterator runs out, instead of stopping.
+    fn repeat_last(self) -> RepeatLast<Self> {
+        RepeatLast {
+            delays: self,
+            last: None,
+        }
+    }
+}
+
+impl<I> DelayExt for I where I: Iterator<Item = Duration> {}
+
+/// Shortens delays to a cap. Created with [`DelayExt::max_delay`].
+#[derive(Clone, Debug)]
+pub struct MaxDelay<I> {
+    delays: I,
+    cap: Duration,
+}
+
+impl<I> Iterator for MaxDelay<I>
+where
+    I: Iterator<Item = Duration>,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        self.delays.next().map(|delay| delay.min(self.cap))
+    }
+}
+
+/// Lengthens delays to a floor. Created with [`DelayExt::min_delay`].
+#[derive(Clone, Debug)]
+pub struct MinDelay<I> {
+    delays: I,
+    floor: Duration,
+}
+
+impl<I> Iterator for MinDelay<I>
+where
+    I: Iterator<Item = Duration>,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        self.delays.next().map(|delay| delay.max(self.floor))
+    }
+}
+
+/// Stops once the sum of delays would exceed a budget. Created with [`DelayExt::max_total`].
+#[derive(Clone, Debug)]
+pub struct MaxTotal<I> {
+    delays: I,
+    budget: Duration,
+    total: Duration,
+}
+
+impl<I> Iterator for MaxTotal<I>
+where
+    I: Iterator<Item = Duration>,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let delay = self.delays.next()?;
+        let total = self.total.checked_add(delay)?;
+
+        if total > self.budget {
+            return None;
+        }
+
+        self.total = total;
+        Some(delay)
+    }
+}
+
+/// Multiplies delays by a factor. Created with [`DelayExt::scale`].
+#[derive(Clone, Debug)]
+pub struct Scale<I> {
+    delays: I,
+    factor: f64,
+}
+
+impl<I> Iterator for Scale<I>
+where
+    I: Iterator<Item = Duration>,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let delay = self.delays.next()?;
+
+        Some(
+            Duration::try_from_secs_f64(delay.as_secs_f64() * self.factor).unwrap_or(Duration::MAX),
+        )
+    }
+}
+
+/// Stops once a delay would end past a time limit. Created with [`DelayExt::take_for`].
+#[derive(Clone, Debug)]
+pub struct TakeFor<I> {
+    delays: I,
+    limit: Duration,
+    start: Option<Instant>,
+}
+
+impl<I> Iterator for TakeFor<I>
+where
+    I: Iterator<Item = Duration>,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let delay = self.delays.next()?;
+
+        if start.elapsed().saturating_add(delay) > self.limit {
+            return None;
+        }
+
+        Some(delay)
+    }
+}
+
+/// Repeats the last delay forever. Created with [`DelayExt::repeat_last`].
+#[derive(Clone, Debug)]
+pub struct RepeatLast<I> {
+    delays: I,
+    last: Option<Duration>,
+}
+
+impl<I> Iterator for RepeatLast<I>
+where
+    I: Iterator<Item = Duration>,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        if let Some(delay) = self.delays.next() {
+            self.last = Some(delay);
+        }
+
+        self.last
+    }
+}
+
+#[test]
+fn max_and_min_delay() {
+    let delays: Vec<_> = super::Exponential::from_millis(10)
+        .min_delay(Duration::from_millis(15))
+        .max_delay(Duration::from_millis(30))
+        .take(4)
+        .collect();
+
+    assert_eq!(delays, [15, 20, 30, 30].map(Duration::from_millis));
+}
+
+#[test]
+fn max_total() {
+    let delays: Vec<_> = super::Fixed::from_millis(40)
+        .max_total(Duration::from_millis(100))
+        .collect();
+
+    assert_eq!(delays, [40, 40].map(Duration::from_millis));
+
+    let mut iter = std::iter::repeat(Duration::MAX).max_total(Duration::MAX);
+    assert_eq!(iter.next(), Some(Duration::MAX));
+    assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn scale() {
+    let mut iter = super::Fixed::from_millis(100).scale(1.5);
+    assert_eq!(iter.next(), Some(Duration::from_millis(150)));
+
+    let mut iter = std::iter::once(Duration::MAX).scale(2.0);
+    assert_eq!(iter.next(), Some(Duration::MAX));
+}
+
+#[test]
+#[should_panic]
+fn scale_wrong_input() {
+    let _panic = super::NoDelay.scale(-1.0);
+}
+
+#[test]
+fn take_for() {
+    let mut iter = super::Fixed::from_millis(10).take_for(Duration::from_secs(1));
+    assert_eq!(iter.next(), Some(Duration::from_millis(10)));
+
+    let mut iter = super::Fixed::from_millis(2000).take_for(Duration::from_secs(1));
+    assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn take_for_starts_on_first_delay() {
+    let iter = super::Fixed::from_millis(10).take_for(Duration::from_millis(100));
+    std::thread::sleep(Duration::from_millis(150));
+
+    let mut iter = iter.clone();
+    assert_eq!(iter.next(), Some(Duration::from_millis(10)));
+}
+
+#[test]
+fn repeat_last() {
+    let delays: Vec<_> = super::Exponential::from_millis(10)
+        .take(2)
+        .repeat_last()
+        .take(4)
+        .collect();
+
+    assert_eq!(delays, [10, 20, 20, 20].map(Duration::from_millis));
+    assert_eq!(std::iter::empty().repeat_last().next(), None);
+}
+
+#[test]
+fn debug_and_clone() {
+    let delays = super::Fixed::from_millis(10)
+        .max_delay(Duration::from_millis(5))
+        .repeat_last();
+
+    assert_eq!(
+        format!("{:?}", delays.clone()),
+        "RepeatLast { delays: MaxDelay { delays: Fixed { duration: 10ms }, cap: 5ms }, last: None }"
+    );
+}
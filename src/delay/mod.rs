@@ -1,15 +1,25 @@
 //! Different types of delay for retryable operations.
+//!
+//! Any of them can be shaped further, such as by capping each delay or the total delay, with the
+//! adapters of [`DelayExt`].
 
 use std::time::Duration;
 
+use crate::{PolicyOptions, RetryPolicy};
+
+mod ext;
 #[cfg(feature = "random")]
 mod random;
 
+pub use ext::{DelayExt, MaxDelay, MaxTotal, MinDelay, RepeatLast, Scale, TakeFor};
 #[cfg(feature = "random")]
-pub use random::{jitter, Range};
+pub use random::{
+    jitter, jitter_with_rng, Decorrelated, EqualJitter, Jitter, PercentageError,
+    ProportionalJitter, Range,
+};
 
 /// Each retry increases the delay since the last exponentially.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Exponential {
     current: u64,
     factor: f64,
@@ -71,6 +81,17 @@ impl From<Duration> for Exponential {
     }
 }
 
+impl From<Exponential> for RetryPolicy {
+    /// Describe the remaining delays of an [`Exponential`] as a [`RetryPolicy`].
+    fn from(exponential: Exponential) -> Self {
+        RetryPolicy::Exponential {
+            base: Duration::from_millis(exponential.current),
+            factor: exponential.factor,
+            options: PolicyOptions::default(),
+        }
+    }
+}
+
 #[test]
 fn exponential_with_factor() {
     let mut iter = Exponential::from_millis_with_factor(1000, 2.0);
@@ -97,7 +118,7 @@ fn exponential_overflow() {
 /// See ["A Performance Comparison of Different Backoff Algorithms under Different Rebroadcast
 /// Probabilities for MANETs"](https://www.researchgate.net/publication/255672213_A_Performance_Comparison_of_Different_Backoff_Algorithms_under_Different_Rebroadcast_Probabilities_for_MANET's)
 /// for more details.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Fibonacci {
     curr: u64,
     next: u64,
@@ -138,6 +159,16 @@ impl From<Duration> for Fibonacci {
     }
 }
 
+impl From<Fibonacci> for RetryPolicy {
+    /// Describe a [`Fibonacci`] as a [`RetryPolicy`], using its next delay as the base.
+    fn from(fibonacci: Fibonacci) -> Self {
+        RetryPolicy::Fibonacci {
+            base: Duration::from_millis(fibonacci.curr),
+            options: PolicyOptions::default(),
+        }
+    }
+}
+
 #[test]
 fn fibonacci() {
     let mut iter = Fibonacci::from_millis(10);
@@ -157,7 +188,7 @@ fn fibonacci_saturated() {
 }
 
 /// Each retry uses a fixed delay.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Fixed {
     duration: Duration,
 }
@@ -186,8 +217,18 @@ impl From<Duration> for Fixed {
     }
 }
 
+impl From<Fixed> for RetryPolicy {
+    /// Describe a [`Fixed`] as a [`RetryPolicy`] with the same delay.
+    fn from(fixed: Fixed) -> Self {
+        RetryPolicy::Fixed {
+            delay: fixed.duration,
+            options: PolicyOptions::default(),
+        }
+    }
+}
+
 /// Each retry happens immediately without any delay.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct NoDelay;
 
 impl Iterator for NoDelay {
@@ -197,3 +238,12 @@ impl Iterator for NoDelay {
         Some(Duration::default())
     }
 }
+
+impl From<NoDelay> for RetryPolicy {
+    /// Describe a [`NoDelay`] as a [`RetryPolicy`].
+    fn from(_: NoDelay) -> Self {
+        RetryPolicy::NoDelay {
+            options: PolicyOptions::default(),
+        }
+    }
+}
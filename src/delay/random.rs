@@ -1,4 +1,6 @@
 use std::{
+    error::Error as StdError,
+    fmt::{Display, Error as FmtError, Formatter},
     ops::{Range as StdRange, RangeInclusive},
     time::Duration,
 };
@@ -6,15 +8,20 @@ use std::{
 use rand::{
     distr::{uniform::Error as UniformError, Distribution, Uniform},
     random,
-    rngs::ThreadRng,
+    rngs::{StdRng, ThreadRng},
+    Rng, SeedableRng,
 };
 
 /// Each retry uses a duration randomly chosen from a range. (When the `random` Cargo feature is
 /// enabled.)
-#[derive(Debug)]
-pub struct Range {
+///
+/// By default, durations are chosen with the thread-local random number generator. To reproduce
+/// the same durations, such as in tests or simulations, use [`Range::with_seed`] or
+/// [`Range::with_rng`].
+#[derive(Clone, Debug)]
+pub struct Range<R = ThreadRng> {
     distribution: Uniform<u64>,
-    rng: ThreadRng,
+    rng: R,
 }
 
 impl Range {
@@ -73,7 +80,25 @@ impl Range {
     }
 }
 
-impl Iterator for Range {
+impl<R> Range<R> {
+    /// Choose durations with the given random number generator instead.
+    #[must_use]
+    pub fn with_rng<S: Rng>(self, rng: S) -> Range<S> {
+        Range {
+            distribution: self.distribution,
+            rng,
+        }
+    }
+
+    /// Choose durations with a random number generator seeded with the given value instead, so
+    /// that the same seed always gives the same durations.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> Range<StdRng> {
+        self.with_rng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<R: Rng> Iterator for Range<R> {
     type Item = Duration;
 
     fn next(&mut self) -> Option<Duration> {
@@ -98,12 +123,306 @@ impl From<RangeInclusive<Duration>> for Range {
     }
 }
 
+/// Each retry uses a random delay between a base delay and three times the previous delay, up to
+/// a cap, as in the "decorrelated jitter" algorithm. (When the `random` Cargo feature is enabled.)
+///
+/// Unlike applying [`jitter`] to another strategy, each delay depends on the one before it, so
+/// the delays grow roughly exponentially while staying spread out between concurrent callers.
+///
+/// See ["Exponential Backoff And
+/// Jitter"](https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/) for more
+/// details.
+#[derive(Clone, Debug)]
+pub struct Decorrelated<R = ThreadRng> {
+    base: u64,
+    cap: u64,
+    previous: u64,
+    rng: R,
+}
+
+impl Decorrelated {
+    /// Create a new [`Decorrelated`] using the given millisecond duration as the shortest delay,
+    /// without a cap.
+    #[must_use]
+    pub fn from_millis(base: u64) -> Self {
+        Self::from_millis_with_cap(base, u64::MAX)
+    }
+
+    /// Create a new [`Decorrelated`] using the given millisecond durations as the shortest delay
+    /// and the longest delay.
+    #[must_use]
+    pub fn from_millis_with_cap(base: u64, cap: u64) -> Self {
+        Decorrelated {
+            base,
+            cap,
+            previous: base,
+            rng: rand::rng(),
+        }
+    }
+}
+
+impl<R> Decorrelated<R> {
+    /// Choose delays with the given random number generator instead.
+    #[must_use]
+    pub fn with_rng<S: Rng>(self, rng: S) -> Decorrelated<S> {
+        Decorrelated {
+            base: self.base,
+            cap: self.cap,
+            previous: self.previous,
+            rng,
+        }
+    }
+
+    /// Choose delays with a random number generator seeded with the given value instead, so that
+    /// the same seed always gives the same delays.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> Decorrelated<StdRng> {
+        self.with_rng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<R: Rng> Iterator for Decorrelated<R> {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let maximum = self.previous.saturating_mul(3).max(self.base);
+        let delay = self.rng.random_range(self.base..=maximum).min(self.cap);
+
+        self.previous = delay;
+
+        Some(Duration::from_millis(delay))
+    }
+}
+
+impl From<Duration> for Decorrelated {
+    fn from(duration: Duration) -> Self {
+        Self::from_millis(duration.as_millis() as u64)
+    }
+}
+
 /// Apply full random jitter to a duration. (When the `random` Cargo feature is enabled.)
 #[must_use]
 pub fn jitter(duration: Duration) -> Duration {
     duration.mul_f64(random::<f64>())
 }
 
+/// Apply full random jitter to a duration, using the given random number generator. With a seeded
+/// generator, this gives reproducible delays. (When the `random` Cargo feature is enabled.)
+///
+/// # Examples
+///
+/// ```rust
+/// use rand::{rngs::StdRng, SeedableRng};
+/// use retry::delay::{jitter_with_rng, Exponential};
+///
+/// let mut rng = StdRng::seed_from_u64(7);
+/// let delays = Exponential::from_millis(10).map(move |delay| jitter_with_rng(delay, &mut rng));
+/// # let _ = delays;
+/// ```
+#[must_use]
+pub fn jitter_with_rng<R: Rng + ?Sized>(duration: Duration, rng: &mut R) -> Duration {
+    duration.mul_f64(rng.random::<f64>())
+}
+
+/// Applies full random jitter to each delay from the given iterator, as with [`jitter`]. Created
+/// with [`DelayExt::jitter`](super::DelayExt::jitter). (When the `random` Cargo feature is
+/// enabled.)
+#[derive(Clone, Debug)]
+pub struct Jitter<I, R = ThreadRng> {
+    delays: I,
+    rng: R,
+}
+
+impl<I> Jitter<I> {
+    pub(crate) fn new(delays: I) -> Self {
+        Jitter {
+            delays,
+            rng: rand::rng(),
+        }
+    }
+}
+
+impl<I, R> Jitter<I, R> {
+    /// Choose delays with the given random number generator instead.
+    #[must_use]
+    pub fn with_rng<S: Rng>(self, rng: S) -> Jitter<I, S> {
+        Jitter {
+            delays: self.delays,
+            rng,
+        }
+    }
+
+    /// Choose delays with a random number generator seeded with the given value instead, so that
+    /// the same seed always gives the same delays.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> Jitter<I, StdRng> {
+        self.with_rng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<I, R> Iterator for Jitter<I, R>
+where
+    I: Iterator<Item = Duration>,
+    R: Rng,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let delay = self.delays.next()?;
+
+        Some(jitter_with_rng(delay, &mut self.rng))
+    }
+}
+
+/// Each delay from the given iterator is replaced by a random delay between half of it and all of
+/// it, as in the "equal jitter" algorithm. (When the `random` Cargo feature is enabled.)
+///
+/// Unlike [`jitter`], which can shorten a delay to nearly nothing, this keeps at least half of
+/// every delay.
+#[derive(Clone, Debug)]
+pub struct EqualJitter<I, R = ThreadRng> {
+    delays: I,
+    rng: R,
+}
+
+impl<I> EqualJitter<I> {
+    /// Create a new [`EqualJitter`] applied to the given delays.
+    #[must_use]
+    pub fn new(delays: I) -> Self {
+        EqualJitter {
+            delays,
+            rng: rand::rng(),
+        }
+    }
+}
+
+impl<I, R> EqualJitter<I, R> {
+    /// Choose delays with the given random number generator instead.
+    #[must_use]
+    pub fn with_rng<S: Rng>(self, rng: S) -> EqualJitter<I, S> {
+        EqualJitter {
+            delays: self.delays,
+            rng,
+        }
+    }
+
+    /// Choose delays with a random number generator seeded with the given value instead, so that
+    /// the same seed always gives the same delays.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> EqualJitter<I, StdRng> {
+        self.with_rng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<I, R> Iterator for EqualJitter<I, R>
+where
+    I: Iterator<Item = Duration>,
+    R: Rng,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let half = self.delays.next()? / 2;
+
+        Some(half + half.mul_f64(self.rng.random::<f64>()))
+    }
+}
+
+/// Each delay from the given iterator is replaced by a random delay within a percentage above or
+/// below it. (When the `random` Cargo feature is enabled.)
+#[derive(Clone, Debug)]
+pub struct ProportionalJitter<I, R = ThreadRng> {
+    delays: I,
+    distribution: Uniform<f64>,
+    rng: R,
+}
+
+impl<I> ProportionalJitter<I> {
+    /// Create a new [`ProportionalJitter`] applied to the given delays, varying each one by up to
+    /// the given percentage, so that `20.0` gives delays between 80% and 120% of the original.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the percentage is not between 0 and 100, inclusive.
+    #[must_use]
+    pub fn new(delays: I, percentage: f64) -> Self {
+        Self::try_new(delays, percentage).expect("percentage must be between 0 and 100")
+    }
+
+    /// Attempt to create a new [`ProportionalJitter`] applied to the given delays, varying each
+    /// one by up to the given percentage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the percentage is not between 0 and 100, inclusive.
+    pub fn try_new(delays: I, percentage: f64) -> Result<Self, PercentageError> {
+        if !(0.0..=100.0).contains(&percentage) {
+            return Err(PercentageError { percentage });
+        }
+
+        let fraction = percentage / 100.0;
+
+        Ok(ProportionalJitter {
+            delays,
+            distribution: Uniform::new_inclusive(1.0 - fraction, 1.0 + fraction)
+                .map_err(|_| PercentageError { percentage })?,
+            rng: rand::rng(),
+        })
+    }
+}
+
+impl<I, R> ProportionalJitter<I, R> {
+    /// Choose delays with the given random number generator instead.
+    #[must_use]
+    pub fn with_rng<S: Rng>(self, rng: S) -> ProportionalJitter<I, S> {
+        ProportionalJitter {
+            delays: self.delays,
+            distribution: self.distribution,
+            rng,
+        }
+    }
+
+    /// Choose delays with a random number generator seeded with the given value instead, so that
+    /// the same seed always gives the same delays.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> ProportionalJitter<I, StdRng> {
+        self.with_rng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<I, R> Iterator for ProportionalJitter<I, R>
+where
+    I: Iterator<Item = Duration>,
+    R: Rng,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let delay = self.delays.next()?;
+
+        Some(delay.mul_f64(self.distribution.sample(&mut self.rng)))
+    }
+}
+
+/// An error from creating a [`ProportionalJitter`] with a percentage that is not between 0 and
+/// 100. (When the `random` Cargo feature is enabled.)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PercentageError {
+    percentage: f64,
+}
+
+impl Display for PercentageError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+        write!(
+            formatter,
+            "jitter percentage must be between 0 and 100, found {}",
+            self.percentage
+        )
+    }
+}
+
+impl StdError for PercentageError {}
+
 #[test]
 fn range_uniform() {
     let mut range = Range::from_millis_exclusive(0, 1);
@@ -138,3 +457,120 @@ fn test_jitter() {
     assert_eq!(Duration::from_millis(0), jitter(Duration::from_millis(0)));
     assert!(Duration::from_millis(0) < jitter(Duration::from_millis(2)));
 }
+
+#[test]
+fn decorrelated_bounds() {
+    let mut previous = 10;
+
+    for delay in Decorrelated::from_millis_with_cap(10, 1000).take(100) {
+        let delay = delay.as_millis() as u64;
+        assert!(delay >= 10);
+        assert!(delay <= 1000);
+        assert!(delay <= previous * 3);
+        previous = delay;
+    }
+}
+
+#[test]
+fn decorrelated_cap() {
+    let mut iter = Decorrelated::from_millis_with_cap(20, 10);
+    assert_eq!(iter.next(), Some(Duration::from_millis(10)));
+    assert_eq!(iter.next(), Some(Duration::from_millis(10)));
+}
+
+#[test]
+fn decorrelated_saturated() {
+    let mut iter = Decorrelated::from(Duration::from_millis(u64::MAX));
+    assert_eq!(iter.next(), Some(Duration::from_millis(u64::MAX)));
+    assert_eq!(iter.next(), Some(Duration::from_millis(u64::MAX)));
+}
+
+#[test]
+fn equal_jitter() {
+    for delay in EqualJitter::new(super::Fixed::from_millis(100)).take(100) {
+        assert!(delay >= Duration::from_millis(50));
+        assert!(delay <= Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn proportional_jitter() {
+    for delay in ProportionalJitter::new(super::Fixed::from_millis(100), 20.0).take(100) {
+        assert!(delay >= Duration::from_millis(80));
+        assert!(delay <= Duration::from_millis(120));
+    }
+
+    let mut iter = ProportionalJitter::new(super::Fixed::from_millis(100), 0.0);
+    assert_eq!(iter.next(), Some(Duration::from_millis(100)));
+}
+
+#[test]
+#[should_panic]
+fn proportional_jitter_wrong_input() {
+    let _panic = ProportionalJitter::new(super::NoDelay, 101.0);
+}
+
+#[test]
+fn try_proportional_jitter() {
+    assert!(ProportionalJitter::try_new(super::NoDelay, 0.0).is_ok());
+    assert!(ProportionalJitter::try_new(super::NoDelay, 100.0).is_ok());
+
+    assert!(ProportionalJitter::try_new(super::NoDelay, -1.0).is_err());
+    assert!(ProportionalJitter::try_new(super::NoDelay, f64::NAN).is_err());
+    assert_eq!(
+        ProportionalJitter::try_new(super::NoDelay, 150.0)
+            .unwrap_err()
+            .to_string(),
+        "jitter percentage must be between 0 and 100, found 150"
+    );
+}
+
+#[test]
+fn seeded_range() {
+    let first: Vec<_> = Range::from_millis_inclusive(0, 1000)
+        .with_seed(42)
+        .take(10)
+        .collect();
+    let second: Vec<_> = Range::from_millis_inclusive(0, 1000)
+        .with_seed(42)
+        .take(10)
+        .collect();
+
+    assert_eq!(first, second);
+    assert_ne!(first, vec![first[0]; 10]);
+}
+
+#[test]
+fn seeded_jitter() {
+    fn delays(seed: u64) -> Vec<Duration> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut delays = Vec::new();
+
+        delays.extend(Decorrelated::from_millis(10).with_seed(seed).take(5));
+        delays.extend(
+            EqualJitter::new(super::Fixed::from_millis(100))
+                .with_seed(seed)
+                .take(5),
+        );
+        delays.extend(
+            ProportionalJitter::new(super::Fixed::from_millis(100), 50.0)
+                .with_seed(seed)
+                .take(5),
+        );
+        delays.extend(
+            super::DelayExt::jitter(super::Fixed::from_millis(100))
+                .with_seed(seed)
+                .take(5),
+        );
+        delays.extend(
+            super::Fixed::from_millis(100)
+                .map(|delay| jitter_with_rng(delay, &mut rng))
+                .take(5),
+        );
+
+        delays
+    }
+
+    assert_eq!(delays(7), delays(7));
+    assert_ne!(delays(7), delays(8));
+}
@@ -0,0 +1,592 @@
+//! Overrides of a retry policy from environment variables.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! use retry::delay::Exponential;
+//! use retry::RetryPolicy;
+//!
+//! // With `MYSVC_RETRY_STRATEGY=fixed` and `MYSVC_RETRY_BASE_MS=250` set, this would wait 250ms
+//! // between tries instead.
+//! let policy = RetryPolicy::from_env("MYSVC_RETRY", Exponential::from_millis(100))?;
+//!
+//! assert_eq!(
+//!     policy,
+//!     RetryPolicy::Exponential {
+//!         base: Duration::from_millis(100),
+//!         factor: 2.0,
+//!         options: Default::default(),
+//!     }
+//! );
+//! # Ok::<(), retry::EnvPolicyError>(())
+//! ```
+
+use std::{
+    env,
+    error::Error as StdError,
+    ffi::OsString,
+    fmt::{Display, Error as FmtError, Formatter},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{policy::valid_factor, RetryPolicy};
+
+/// An error from reading a [`RetryPolicy`] from environment variables with
+/// [`RetryPolicy::from_env`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EnvPolicyError {
+    /// A variable is set to a value that cannot be used.
+    InvalidValue {
+        /// The name of the variable.
+        variable: String,
+        /// The value of the variable, with any invalid Unicode replaced.
+        value: String,
+        /// A description of the values that are accepted.
+        expected: &'static str,
+    },
+    /// A variable needed by the chosen strategy is not set, and cannot be taken from the default
+    /// policy.
+    MissingVariable {
+        /// The name of the variable.
+        variable: String,
+    },
+    /// A variable is set, but does not apply to the chosen strategy.
+    UnusedVariable {
+        /// The name of the variable.
+        variable: String,
+        /// The name of the chosen strategy.
+        strategy: &'static str,
+    },
+    /// A variable is set, but needs a Cargo feature that is not enabled.
+    DisabledFeature {
+        /// The name of the variable.
+        variable: String,
+        /// The name of the Cargo feature.
+        feature: &'static str,
+    },
+    /// The policy given as the default cannot be used, and no variable replaces the invalid
+    /// part.
+    InvalidDefault {
+        /// A description of the problem.
+        reason: String,
+    },
+}
+
+impl Display for EnvPolicyError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            EnvPolicyError::InvalidValue {
+                variable,
+                value,
+                expected,
+            } => write!(
+                formatter,
+                "invalid value `{}` for {}, expected {}",
+                value, variable, expected
+            ),
+            EnvPolicyError::MissingVariable { variable } => {
+                write!(formatter, "{} must be set", variable)
+            }
+            EnvPolicyError::UnusedVariable { variable, strategy } => write!(
+                formatter,
+                "{} does not apply to the `{}` strategy",
+                variable, strategy
+            ),
+            EnvPolicyError::DisabledFeature { variable, feature } => write!(
+                formatter,
+                "{} requires the `{}` Cargo feature",
+                variable, feature
+            ),
+            EnvPolicyError::InvalidDefault { reason } => {
+                write!(formatter, "invalid default policy: {}", reason)
+            }
+        }
+    }
+}
+
+impl StdError for EnvPolicyError {}
+
+/// The environment variables under a prefix.
+struct Variables<'a, L> {
+    prefix: &'a str,
+    lookup: L,
+}
+
+impl<L> Variables<'_, L>
+where
+    L: Fn(&str) -> Option<OsString>,
+{
+    fn name(&self, suffix: &str) -> String {
+        format!("{}_{}", self.prefix, suffix)
+    }
+
+    /// The value of the variable with the given suffix, treating an empty value as unset.
+    fn get(&self, suffix: &str, expected: &'static str) -> Result<Option<String>, EnvPolicyError> {
+        let name = self.name(suffix);
+
+        match (self.lookup)(&name) {
+            None => Ok(None),
+            Some(value) if value.is_empty() => Ok(None),
+            Some(value) => {
+                value
+                    .into_string()
+                    .map(Some)
+                    .map_err(|value| EnvPolicyError::InvalidValue {
+                        variable: name,
+                        value: value.to_string_lossy().into_owned(),
+                        expected,
+                    })
+            }
+        }
+    }
+
+    fn parse<T: FromStr>(
+        &self,
+        suffix: &str,
+        expected: &'static str,
+    ) -> Result<Option<T>, EnvPolicyError> {
+        self.parse_where(suffix, expected, |_| true)
+    }
+
+    /// Like [`parse`](Self::parse), but also rejects values that fail the given check.
+    fn parse_where<T: FromStr>(
+        &self,
+        suffix: &str,
+        expected: &'static str,
+        valid: impl Fn(&T) -> bool,
+    ) -> Result<Option<T>, EnvPolicyError> {
+        let Some(value) = self.get(suffix, expected)? else {
+            return Ok(None);
+        };
+
+        value
+            .trim()
+            .parse()
+            .ok()
+            .filter(valid)
+            .map(Some)
+            .ok_or_else(|| EnvPolicyError::InvalidValue {
+                variable: self.name(suffix),
+                value,
+                expected,
+            })
+    }
+
+    fn millis(&self, suffix: &str) -> Result<Option<Duration>, EnvPolicyError> {
+        Ok(self
+            .parse(suffix, "a whole number of milliseconds")?
+            .map(Duration::from_millis))
+    }
+
+    fn unused(&self, suffix: &str, policy: &RetryPolicy) -> EnvPolicyError {
+        EnvPolicyError::UnusedVariable {
+            variable: self.name(suffix),
+            strategy: policy.strategy(),
+        }
+    }
+
+    /// Reject the variable with the given suffix if it is set, as it needs a disabled feature.
+    #[cfg(not(feature = "random"))]
+    fn disabled(&self, suffix: &str, feature: &'static str) -> Result<(), EnvPolicyError> {
+        match self.get(suffix, "")? {
+            Some(_) => Err(EnvPolicyError::DisabledFeature {
+                variable: self.name(suffix),
+                feature,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Read a policy from environment variables whose names start with the given prefix,
+    /// starting from the given default policy, which may also be a [`Fixed`](crate::delay::Fixed),
+    /// [`Exponential`](crate::delay::Exponential), [`Fibonacci`](crate::delay::Fibonacci) or
+    /// [`NoDelay`](crate::delay::NoDelay) delay. This allows delays to be changed without a new
+    /// build, such as during an incident.
+    ///
+    /// With the prefix `MYSVC_RETRY`, the following variables are read. Each one that is set
+    /// overrides the corresponding part of the default policy, and an empty variable is treated
+    /// as unset.
+    ///
+    /// - `MYSVC_RETRY_STRATEGY`: one of `no_delay`, `fixed`, `exponential`, `fibonacci` or
+    ///   `range`. If the strategy differs from the default, its delays are taken from the
+    ///   default where possible, and its limits are kept.
+    /// - `MYSVC_RETRY_BASE_MS`: the delay of `fixed`, or the first delay of `exponential` and
+    ///   `fibonacci`, in milliseconds.
+    /// - `MYSVC_RETRY_FACTOR`: the factor of `exponential`, a finite number greater than zero.
+    /// - `MYSVC_RETRY_MIN_MS` and `MYSVC_RETRY_MAX_MS`: the bounds of `range`, in milliseconds.
+    /// - `MYSVC_RETRY_MAX_TRIES`: the most times the operation is tried.
+    /// - `MYSVC_RETRY_MAX_DELAY_MS`: the longest delay between tries, in milliseconds.
+    /// - `MYSVC_RETRY_MAX_TOTAL_DELAY_MS`: the most time spent waiting between tries, in
+    ///   milliseconds.
+    /// - `MYSVC_RETRY_JITTER`: `true` or `false`, whether to apply full random jitter.
+    ///
+    /// `MYSVC_RETRY_MIN_MS`, `MYSVC_RETRY_MAX_MS` and `MYSVC_RETRY_JITTER` need the `random` Cargo
+    /// feature, and are rejected if they are set without it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnvPolicyError`] if a variable has an invalid value, if a variable needed by
+    /// the chosen strategy is neither set nor available from the default policy, if a variable
+    /// does not apply to the chosen strategy or needs a disabled Cargo feature, or if the
+    /// resulting policy is invalid because of the default, such as an `exponential` factor that
+    /// is not greater than zero.
+    pub fn from_env<P>(prefix: &str, default: P) -> Result<RetryPolicy, EnvPolicyError>
+    where
+        P: Into<RetryPolicy>,
+    {
+        Self::from_variables(prefix, default.into(), |name| env::var_os(name))
+    }
+
+    fn from_variables<L>(
+        prefix: &str,
+        mut policy: RetryPolicy,
+        lookup: L,
+    ) -> Result<RetryPolicy, EnvPolicyError>
+    where
+        L: Fn(&str) -> Option<OsString>,
+    {
+        let variables = Variables { prefix, lookup };
+        let base = variables.millis("BASE_MS")?;
+        let factor =
+            variables.parse_where("FACTOR", "a finite number greater than zero", |factor| {
+                valid_factor(*factor).is_ok()
+            })?;
+        #[cfg(feature = "random")]
+        let min = variables.millis("MIN_MS")?;
+        #[cfg(feature = "random")]
+        let max = variables.millis("MAX_MS")?;
+        #[cfg(not(feature = "random"))]
+        for suffix in ["MIN_MS", "MAX_MS", "JITTER"] {
+            variables.disabled(suffix, "random")?;
+        }
+
+        if let Some(strategy) = variables.get("STRATEGY", "a strategy name")? {
+            let name = strategy.trim();
+            if name != policy.strategy() {
+                let options = *policy.options();
+                let base = || {
+                    base.or_else(|| policy.base())
+                        .ok_or_else(|| EnvPolicyError::MissingVariable {
+                            variable: variables.name("BASE_MS"),
+                        })
+                };
+
+                policy = match name {
+                    "no_delay" => RetryPolicy::NoDelay { options },
+                    "fixed" => RetryPolicy::Fixed {
+                        delay: base()?,
+                        options,
+                    },
+                    "exponential" => RetryPolicy::Exponential {
+                        base: base()?,
+                        factor: 2.0,
+                        options,
+                    },
+                    "fibonacci" => RetryPolicy::Fibonacci {
+                        base: base()?,
+                        options,
+                    },
+                    #[cfg(feature = "random")]
+                    "range" => {
+                        let required = |bound: Option<Duration>, suffix| {
+                            bound.ok_or_else(|| EnvPolicyError::MissingVariable {
+                                variable: variables.name(suffix),
+                            })
+                        };
+
+                        RetryPolicy::Range {
+                            min: required(min, "MIN_MS")?,
+                            max: required(max, "MAX_MS")?,
+                            options,
+                        }
+                    }
+                    _ => {
+                        return Err(EnvPolicyError::InvalidValue {
+                            variable: variables.name("STRATEGY"),
+                            value: name.to_owned(),
+                            expected: if cfg!(feature = "random") {
+                                "one of `no_delay`, `fixed`, `exponential`, `fibonacci` or `range`"
+                            } else {
+                                "one of `no_delay`, `fixed`, `exponential` or `fibonacci`"
+                            },
+                        })
+                    }
+                };
+            }
+        }
+
+        if let Some(new_base) = base {
+            match &mut policy {
+                RetryPolicy::Fixed { delay: base, .. }
+                | RetryPolicy::Exponential { base, .. }
+                | RetryPolicy::Fibonacci { base, .. } => *base = new_base,
+                _ => return Err(variables.unused("BASE_MS", &policy)),
+            }
+        }
+
+        if let Some(new_factor) = factor {
+            match &mut policy {
+                RetryPolicy::Exponential { factor, .. } => *factor = new_factor,
+                _ => return Err(variables.unused("FACTOR", &policy)),
+            }
+        }
+
+        #[cfg(feature = "random")]
+        if min.is_some() || max.is_some() {
+            let RetryPolicy::Range {
+                min: range_min,
+                max: range_max,
+                ..
+            } = &mut policy
+            else {
+                let suffix = if min.is_some() { "MIN_MS" } else { "MAX_MS" };
+                return Err(variables.unused(suffix, &policy));
+            };
+
+            *range_min = min.unwrap_or(*range_min);
+            *range_max = max.unwrap_or(*range_max);
+
+            if range_min > range_max {
+                let suffix = if max.is_some() { "MAX_MS" } else { "MIN_MS" };
+                return Err(EnvPolicyError::InvalidValue {
+                    variable: variables.name(suffix),
+                    value: variables.get(suffix, "")?.unwrap_or_default(),
+                    expected: "a range whose minimum is no greater than its maximum",
+                });
+            }
+        }
+
+        let max_tries = variables.parse("MAX_TRIES", "a whole number of tries")?;
+        let max_delay = variables.millis("MAX_DELAY_MS")?;
+        let max_total_delay = variables.millis("MAX_TOTAL_DELAY_MS")?;
+        #[cfg(feature = "random")]
+        let jitter = variables.parse("JITTER", "`true` or `false`")?;
+
+        let options = policy.options_mut();
+        options.max_tries = max_tries.or(options.max_tries);
+        options.max_delay = max_delay.or(options.max_delay);
+        options.max_total_delay = max_total_delay.or(options.max_total_delay);
+        #[cfg(feature = "random")]
+        {
+            options.jitter = jitter.unwrap_or(options.jitter);
+        }
+
+        policy
+            .validate()
+            .map_err(|reason| EnvPolicyError::InvalidDefault { reason })?;
+
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, ffi::OsString, time::Duration};
+
+    use super::EnvPolicyError;
+    use crate::delay::{Exponential, Fixed, NoDelay};
+    use crate::{PolicyOptions, RetryPolicy};
+
+    fn from_variables<P: Into<RetryPolicy>>(
+        variables: &[(&str, &str)],
+        default: P,
+    ) -> Result<RetryPolicy, EnvPolicyError> {
+        let variables: HashMap<_, _> = variables
+            .iter()
+            .map(|(name, value)| (format!("TEST_RETRY_{}", name), OsString::from(value)))
+            .collect();
+
+        RetryPolicy::from_variables("TEST_RETRY", default.into(), |name| {
+            variables.get(name).cloned()
+        })
+    }
+
+    #[test]
+    fn falls_back_to_default() {
+        let policy = from_variables(&[("BASE_MS", "")], Fixed::from_millis(100)).unwrap();
+
+        assert_eq!(
+            policy,
+            RetryPolicy::Fixed {
+                delay: Duration::from_millis(100),
+                options: PolicyOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn overrides_default() {
+        let policy = from_variables(
+            &[
+                ("STRATEGY", "exponential"),
+                ("FACTOR", "3"),
+                ("MAX_TRIES", "8"),
+                ("MAX_DELAY_MS", "10000"),
+            ],
+            Fixed::from_millis(100),
+        )
+        .unwrap();
+
+        assert_eq!(
+            policy,
+            RetryPolicy::Exponential {
+                base: Duration::from_millis(100),
+                factor: 3.0,
+                options: PolicyOptions {
+                    max_tries: Some(8),
+                    max_delay: Some(Duration::from_secs(10)),
+                    ..PolicyOptions::default()
+                },
+            }
+        );
+
+        let policy = from_variables(&[("BASE_MS", "250")], Exponential::from_millis(10)).unwrap();
+        assert_eq!(
+            policy.delays().take(2).collect::<Vec<_>>(),
+            vec![Duration::from_millis(250), Duration::from_millis(500)]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_variables() {
+        assert_eq!(
+            from_variables(&[("MAX_TRIES", "many")], NoDelay).unwrap_err(),
+            EnvPolicyError::InvalidValue {
+                variable: "TEST_RETRY_MAX_TRIES".to_owned(),
+                value: "many".to_owned(),
+                expected: "a whole number of tries",
+            }
+        );
+        for value in ["-3", "0", "NaN", "inf"] {
+            assert_eq!(
+                from_variables(&[("FACTOR", value)], Exponential::from_millis(1)).unwrap_err(),
+                EnvPolicyError::InvalidValue {
+                    variable: "TEST_RETRY_FACTOR".to_owned(),
+                    value: value.to_owned(),
+                    expected: "a finite number greater than zero",
+                }
+            );
+        }
+        assert_eq!(
+            from_variables(&[("STRATEGY", "fixed")], NoDelay).unwrap_err(),
+            EnvPolicyError::MissingVariable {
+                variable: "TEST_RETRY_BASE_MS".to_owned(),
+            }
+        );
+        assert_eq!(
+            from_variables(&[("FACTOR", "2")], Fixed::from_millis(1))
+                .unwrap_err()
+                .to_string(),
+            "TEST_RETRY_FACTOR does not apply to the `fixed` strategy"
+        );
+        assert_eq!(
+            from_variables(&[("STRATEGY", "linear")], NoDelay)
+                .unwrap_err()
+                .to_string(),
+            if cfg!(feature = "random") {
+                "invalid value `linear` for TEST_RETRY_STRATEGY, expected one of `no_delay`, \
+                 `fixed`, `exponential`, `fibonacci` or `range`"
+            } else {
+                "invalid value `linear` for TEST_RETRY_STRATEGY, expected one of `no_delay`, \
+                 `fixed`, `exponential` or `fibonacci`"
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_default() {
+        let error = from_variables(&[], Exponential::from_millis_with_factor(10, 0.0)).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "invalid default policy: the factor of `exponential` must be a finite number greater \
+             than zero, found 0"
+        );
+        assert!(from_variables(
+            &[("FACTOR", "2")],
+            Exponential::from_millis_with_factor(10, 0.0)
+        )
+        .is_ok());
+        assert!(matches!(
+            from_variables(
+                &[("STRATEGY", "fibonacci")],
+                RetryPolicy::Fixed {
+                    delay: Duration::from_micros(1500),
+                    options: PolicyOptions::default(),
+                }
+            ),
+            Err(EnvPolicyError::InvalidDefault { .. })
+        ));
+    }
+
+    #[cfg(not(feature = "random"))]
+    #[test]
+    fn rejects_random_variables_without_feature() {
+        for name in ["MIN_MS", "MAX_MS", "JITTER"] {
+            assert_eq!(
+                from_variables(&[(name, "1")], NoDelay).unwrap_err(),
+                EnvPolicyError::DisabledFeature {
+                    variable: format!("TEST_RETRY_{}", name),
+                    feature: "random",
+                }
+            );
+        }
+        assert_eq!(
+            from_variables(&[("JITTER", "true")], NoDelay)
+                .unwrap_err()
+                .to_string(),
+            "TEST_RETRY_JITTER requires the `random` Cargo feature"
+        );
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn overrides_random_policies() {
+        let policy = from_variables(
+            &[
+                ("STRATEGY", "range"),
+                ("MIN_MS", "10"),
+                ("MAX_MS", "20"),
+                ("JITTER", "true"),
+            ],
+            NoDelay,
+        )
+        .unwrap();
+
+        assert_eq!(
+            policy,
+            RetryPolicy::Range {
+                min: Duration::from_millis(10),
+                max: Duration::from_millis(20),
+                options: PolicyOptions {
+                    jitter: true,
+                    ..PolicyOptions::default()
+                },
+            }
+        );
+
+        assert_eq!(
+            from_variables(&[("STRATEGY", "range"), ("MIN_MS", "10")], NoDelay).unwrap_err(),
+            EnvPolicyError::MissingVariable {
+                variable: "TEST_RETRY_MAX_MS".to_owned(),
+            }
+        );
+        assert_eq!(
+            from_variables(
+                &[("STRATEGY", "range"), ("MIN_MS", "30"), ("MAX_MS", "20")],
+                NoDelay
+            )
+            .unwrap_err(),
+            EnvPolicyError::InvalidValue {
+                variable: "TEST_RETRY_MAX_MS".to_owned(),
+                value: "20".to_owned(),
+                expected: "a range whose minimum is no greater than its maximum",
+            }
+        );
+    }
+}
@@ -0,0 +1,493 @@
+//! Hedged requests, which run speculative tries in parallel.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::{thread, time::Duration};
+//! # use std::sync::atomic::{AtomicU64, Ordering};
+//! # use retry::delay::Fixed;
+//! use retry::hedge;
+//!
+//! let tries = AtomicU64::new(0);
+//!
+//! let result = hedge(Fixed::from_millis(10).take(2), 2, move || {
+//!     if tries.fetch_add(1, Ordering::SeqCst) == 0 {
+//!         // The first try stalls, so a second one is started after 10ms.
+//!         thread::sleep(Duration::from_secs(1));
+//!     }
+//!     Ok::<_, &str>("replica")
+//! });
+//!
+//! assert_eq!(result, Ok("replica"));
+//! ```
+
+use std::{
+    any::Any,
+    future::{poll_fn, Future},
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    pin::Pin,
+    sync::{mpsc, Arc},
+    task::Poll,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{AsyncSleeper, Error, ErrorKind, OperationResult};
+
+type Finished<R, E> = (Result<OperationResult<R, E>, Box<dyn Any + Send>>, Duration);
+type Timer<S> = Option<(Pin<Box<S>>, Duration)>;
+
+/// Run the given operation, starting another try in parallel each time a delay from the given
+/// [`Duration`] iterator passes without a result, and return the first successful result.
+///
+/// This is meant for idempotent operations such as reads, where a slow try can be raced by a
+/// speculative one. At most `max_in_flight` tries run at once; a value of zero is treated as one.
+/// A try that fails with [`OperationResult::Retry`] frees its slot, and the next try is started
+/// once its delay has passed since the failure, as with [`retry`](crate::retry). A try that fails
+/// with [`OperationResult::RetryAfter`] frees its slot too, and the requested delay is waited for
+/// in place of the next delay from the iterator. Either way, the iterator limits the total number
+/// of tries.
+///
+/// A try that fails with [`OperationResult::Err`] stops all hedging and its error is returned
+/// immediately with the kind [`ErrorKind::Fatal`]. Otherwise, the error from the last try to
+/// finish is returned with the kind [`ErrorKind::Exhausted`] once every try has failed. Its
+/// [`Error::operation_time`] counts each try in full, even where tries overlapped.
+///
+/// Each try runs on a new thread. Tries that are still running when a result is returned are left
+/// to finish in the background, and their results are discarded.
+///
+/// # Panics
+///
+/// If the operation panics, the panic is propagated to the caller.
+pub fn hedge<I, O, R, E, OR>(iterable: I, max_in_flight: usize, operation: O) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    O: Fn() -> OR + Send + Sync + 'static,
+    OR: Into<OperationResult<R, E>>,
+    R: Send + 'static,
+    E: Send + 'static,
+{
+    let max_in_flight = max_in_flight.max(1);
+    let operation = Arc::new(operation);
+    let (sender, receiver) = mpsc::channel::<Finished<R, E>>();
+    let launch = || {
+        let operation = Arc::clone(&operation);
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let started_at = Instant::now();
+            let result = catch_unwind(AssertUnwindSafe(|| operation().into()));
+            let _ = sender.send((result, started_at.elapsed()));
+        });
+    };
+
+    let mut delays = iterable.into_iter();
+    let start = Instant::now();
+    let mut tries = 1;
+    let mut in_flight = 1;
+    let mut total_delay = Duration::default();
+    let mut operation_time = Duration::default();
+    let mut schedule = delays.next().and_then(after);
+    let mut requested = false;
+    launch();
+
+    loop {
+        let finished = match schedule {
+            Some((launch_at, delay)) if in_flight < max_in_flight => {
+                let timeout = launch_at.saturating_duration_since(Instant::now());
+                match receiver.recv_timeout(timeout) {
+                    Ok(finished) => finished,
+                    Err(_) => {
+                        launch();
+                        tries += 1;
+                        in_flight += 1;
+                        total_delay = total_delay.saturating_add(delay);
+                        requested = false;
+                        schedule = delays.next().and_then(after);
+                        continue;
+                    }
+                }
+            }
+            _ => receiver.recv().expect("hedged tries always send a result"),
+        };
+
+        let (result, duration) = finished;
+        in_flight -= 1;
+        operation_time += duration;
+
+        let (error, kind) = match result.unwrap_or_else(|panic| resume_unwind(panic)) {
+            OperationResult::Ok(value) => return Ok(value),
+            OperationResult::Retry(error) => {
+                if let Some((_, delay)) = schedule {
+                    if !requested {
+                        schedule = after(delay);
+                    }
+                }
+
+                if schedule.is_some() || in_flight > 0 {
+                    continue;
+                }
+
+                (error, ErrorKind::Exhausted)
+            }
+            OperationResult::RetryAfter(error, delay) => {
+                if schedule.is_some() {
+                    schedule = after(delay);
+                    requested = true;
+                }
+
+                if schedule.is_some() || in_flight > 0 {
+                    continue;
+                }
+
+                (error, ErrorKind::Exhausted)
+            }
+            OperationResult::Err(error) => (error, ErrorKind::Fatal),
+        };
+
+        return Err(Error {
+            error,
+            total_delay,
+            tries,
+            operation_time,
+            elapsed: start.elapsed(),
+            kind,
+            timeouts: 0,
+            attempts: Vec::new(),
+        });
+    }
+}
+
+/// The time the given delay from now ends, along with the delay, or `None` if it is too far in
+/// the future to represent, in which case no further tries are started.
+fn after(delay: Duration) -> Option<(Instant, Duration)> {
+    Instant::now().checked_add(delay).map(|at| (at, delay))
+}
+
+/// Run the given asynchronous operation, starting another try concurrently each time a delay
+/// from the given [`Duration`] iterator passes without a result, waiting with the given
+/// [`AsyncSleeper`], and return the first successful result.
+///
+/// This behaves like [`hedge`], except that the tries run concurrently within the returned future
+/// rather than on separate threads. Tries that are still running when a result is returned are
+/// dropped, cancelling them.
+pub async fn hedge_async<I, S, O, F, R, E, OR>(
+    iterable: I,
+    sleeper: S,
+    max_in_flight: usize,
+    mut operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    S: AsyncSleeper,
+    O: FnMut() -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    let max_in_flight = max_in_flight.max(1);
+    let mut delays = iterable.into_iter();
+    let start = Instant::now();
+    let mut tries = 1;
+    let mut total_delay = Duration::default();
+    let mut in_flight: Vec<(Pin<Box<F>>, Instant)> = vec![(Box::pin(operation()), start)];
+    let mut operation_time = Duration::default();
+    let sleep = |delay| (Box::pin(sleeper.sleep(delay)), delay);
+    let mut timer: Timer<S::Sleep> = delays.next().map(sleep);
+    let mut requested = false;
+    let mut last_error = None;
+
+    poll_fn(|context| loop {
+        let mut index = 0;
+
+        while index < in_flight.len() {
+            let Poll::Ready(result) = in_flight[index].0.as_mut().poll(context) else {
+                index += 1;
+                continue;
+            };
+
+            let (_, started_at) = in_flight.swap_remove(index);
+            operation_time += started_at.elapsed();
+
+            let error = match result.into() {
+                OperationResult::Ok(value) => return Poll::Ready(Ok(value)),
+                OperationResult::Retry(error) => {
+                    last_error = Some(error);
+                    if let Some((_, delay)) = timer {
+                        if !requested {
+                            timer = Some(sleep(delay));
+                        }
+                    }
+                    continue;
+                }
+                OperationResult::RetryAfter(error, delay) => {
+                    last_error = Some(error);
+                    if timer.is_some() {
+                        timer = Some(sleep(delay));
+                        requested = true;
+                    }
+                    continue;
+                }
+                OperationResult::Err(error) => error,
+            };
+
+            return Poll::Ready(Err(Error {
+                error,
+                total_delay,
+                tries,
+                operation_time,
+                elapsed: start.elapsed(),
+                kind: ErrorKind::Fatal,
+                timeouts: 0,
+                attempts: Vec::new(),
+            }));
+        }
+
+        let launch = match &mut timer {
+            None if in_flight.is_empty() => {
+                return Poll::Ready(Err(Error {
+                    error: last_error.take().expect("every hedged try has failed"),
+                    total_delay,
+                    tries,
+                    operation_time,
+                    elapsed: start.elapsed(),
+                    kind: ErrorKind::Exhausted,
+                    timeouts: 0,
+                    attempts: Vec::new(),
+                }));
+            }
+            Some((sleep, delay)) if in_flight.len() < max_in_flight => {
+                let elapsed = sleep.as_mut().poll(context).is_ready();
+                if elapsed {
+                    total_delay = total_delay.saturating_add(*delay);
+                }
+                elapsed
+            }
+            _ => false,
+        };
+
+        if !launch {
+            return Poll::Pending;
+        }
+
+        in_flight.push((Box::pin(operation()), Instant::now()));
+        tries += 1;
+        requested = false;
+        timer = delays.next().map(sleep);
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        future::{pending, ready},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{hedge, hedge_async};
+    use crate::asynchronous::tests::{block_on, no_sleep};
+    use crate::delay::{Fixed, NoDelay};
+    use crate::{ErrorKind, OperationResult};
+
+    #[test]
+    fn returns_first_success() {
+        let tries = AtomicU64::new(0);
+
+        let value = hedge(Fixed::from_millis(10), 2, move || {
+            let current_try = tries.fetch_add(1, Ordering::SeqCst) + 1;
+            if current_try == 1 {
+                thread::sleep(Duration::from_secs(1));
+            }
+            Ok::<_, ()>(current_try)
+        })
+        .unwrap();
+
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn fatal_error_stops_hedging() {
+        let tries = AtomicU64::new(0);
+
+        let res = hedge(Fixed::from_millis(10), 3, move || {
+            let current_try = tries.fetch_add(1, Ordering::SeqCst) + 1;
+            if current_try == 1 {
+                thread::sleep(Duration::from_secs(1));
+                return OperationResult::Ok(());
+            }
+            OperationResult::Err(current_try)
+        });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, 2);
+        assert_eq!(err.tries, 2);
+        assert_eq!(err.kind, ErrorKind::Fatal);
+    }
+
+    #[test]
+    fn exhausts_delays() {
+        let res = hedge(NoDelay.take(3), 2, || Err::<(), _>("fail"));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, "fail");
+        assert_eq!(err.tries, 4);
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+    }
+
+    #[test]
+    fn limits_tries_in_flight() {
+        let running = Arc::new(AtomicU64::new(0));
+        let most_running = Arc::new(AtomicU64::new(0));
+        let (running_clone, most_running_clone) = (Arc::clone(&running), Arc::clone(&most_running));
+
+        let res = hedge(NoDelay.take(5), 2, move || {
+            let now_running = running_clone.fetch_add(1, Ordering::SeqCst) + 1;
+            most_running_clone.fetch_max(now_running, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            running_clone.fetch_sub(1, Ordering::SeqCst);
+            Err::<(), _>("fail")
+        });
+
+        assert_eq!(res.unwrap_err().tries, 6);
+        assert_eq!(most_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn waits_for_requested_delay() {
+        let start = Instant::now();
+
+        let res = hedge(Fixed::from_millis(10_000).take(2), 1, || {
+            OperationResult::<(), _>::RetryAfter("busy", Duration::from_millis(50))
+        });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.total_delay, Duration::from_millis(100));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn waits_between_failed_tries() {
+        let start = Instant::now();
+
+        let res = hedge(Fixed::from_millis(20).take(4), 1, || Err::<(), _>("fail"));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 5);
+        assert_eq!(err.total_delay, Duration::from_millis(80));
+        assert!(err.elapsed >= err.total_delay);
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn propagates_panics() {
+        let _ = hedge(NoDelay.take(1), 1, || -> Result<(), ()> { panic!("boom") });
+    }
+
+    #[test]
+    fn async_returns_first_success() {
+        let mut current_try = 0;
+
+        let value = block_on(hedge_async(NoDelay, no_sleep, 2, || {
+            current_try += 1;
+            let current_try = current_try;
+            async move {
+                if current_try == 1 {
+                    pending::<()>().await;
+                }
+                Ok::<_, ()>(current_try)
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn async_fatal_error_stops_hedging() {
+        let mut current_try = 0;
+
+        let res = block_on(hedge_async(NoDelay, no_sleep, 3, || {
+            current_try += 1;
+            let current_try = current_try;
+            async move {
+                if current_try == 1 {
+                    pending::<()>().await;
+                }
+                OperationResult::<(), _>::Err(current_try)
+            }
+        }));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, 2);
+        assert_eq!(err.tries, 2);
+        assert_eq!(err.kind, ErrorKind::Fatal);
+    }
+
+    #[test]
+    fn async_waits_for_requested_delay() {
+        let slept = RefCell::new(Vec::new());
+        let sleeper = |delay| {
+            slept.borrow_mut().push(delay);
+            ready(())
+        };
+
+        let res = block_on(hedge_async(
+            Fixed::from_millis(10_000).take(1),
+            sleeper,
+            1,
+            || {
+                ready(OperationResult::<(), _>::RetryAfter(
+                    "busy",
+                    Duration::from_secs(5),
+                ))
+            },
+        ));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 2);
+        assert_eq!(err.total_delay, Duration::from_secs(5));
+        assert_eq!(
+            slept.into_inner(),
+            vec![Duration::from_secs(10), Duration::from_secs(5)]
+        );
+    }
+
+    #[test]
+    fn async_waits_between_failed_tries() {
+        let slept = RefCell::new(Vec::new());
+        let sleeper = |delay| {
+            slept.borrow_mut().push(delay);
+            ready(())
+        };
+
+        let res = block_on(hedge_async(
+            Fixed::from_millis(20).take(2),
+            sleeper,
+            1,
+            || ready(Err::<(), _>("fail")),
+        ));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.total_delay, Duration::from_millis(40));
+        assert_eq!(slept.into_inner(), vec![Duration::from_millis(20); 4]);
+    }
+
+    #[test]
+    fn async_exhausts_delays() {
+        let res = block_on(hedge_async(NoDelay.take(2), no_sleep, 2, || {
+            ready(Err::<(), _>("fail"))
+        }));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+    }
+}
@@ -94,6 +94,29 @@ assert!(result.is_ok());
 //! assert_eq!(value, 2);
 //! ```
 //!
+//! Operations that return a plain [`Result`] can instead be classified with the [`retry_if`]
+//! function, whose predicate decides which errors are retried. Errors it rejects are returned
+//! immediately.
+//!
+//! ```
+//! # use retry::retry_if;
+//! # use retry::delay::Fixed;
+//! use std::io::{Error, ErrorKind};
+//!
+//! let result = retry_if(
+//!     Fixed::from_millis(1),
+//!     |error: &Error| error.kind() == ErrorKind::Interrupted,
+//!     || Err::<(), _>(Error::from(ErrorKind::NotFound)),
+//! );
+//!
+//! assert_eq!(result.unwrap_err().tries, 1);
+//! ```
+//!
+//! When the operation knows how long to wait before the next try, such as from an HTTP
+//! `Retry-After` header, return [`OperationResult::RetryAfter`] with the requested delay. It is
+//! used in place of the next delay from the iterator. The [`Retry`] builder can cap requested
+//! delays with [`Retry::max_retry_after`].
+//!
 //! If your operation needs to know how many times it's been tried, use the [`retry_with_index`]
 //! function. This works the same as [`retry`], but passes the number of the current try to the
 //! closure as an argument.
@@ -119,24 +142,143 @@ assert!(result.is_ok());
 //! assert!(result.is_err());
 //! ```
 //!
+//! Asynchronous operations can be retried with the [`retry_async`] and [`retry_async_with_index`]
+//! functions. These take a closure that returns a [`Future`](std::future::Future) and an
+//! [`AsyncSleeper`] used to wait between tries without blocking the thread. Any function that
+//! takes a [`Duration`] and returns a future, such as the sleep function of your async runtime,
+//! can be used as an [`AsyncSleeper`]. With the `tokio` Cargo feature enabled, the
+//! `retry::tokio` module provides versions of [`retry`] and [`retry_with_index`] that wait using
+//! Tokio's timer.
+//!
+//! To tune delays without recompiling, describe them with a [`RetryPolicy`], which covers the
+//! strategies in the [`delay`] module along with jitter and limits on tries, delays and total
+//! delay. It can be parsed from a compact string such as `exponential(100ms, max=10s, tries=8)`,
+//! and with the `serde` Cargo feature enabled, read from configuration files. A policy can be
+//! passed to [`retry`] in place of a [`Duration`] iterator. [`RetryPolicy::from_env`] overrides a
+//! default policy from environment variables, so that delays can be changed during an incident
+//! without a new build.
+//!
+//! Synchronous retries wait between tries by blocking the current thread. To substitute a
+//! different way of waiting, such as a [`ManualClock`] that only records the requested delays in
+//! tests, use the [`retry_with_sleeper`] function with any implementation of [`Sleeper`].
+//!
+//! To bound the total time spent retrying rather than the number of tries, use the
+//! [`retry_with_deadline`] function. Unlike limiting the [`Duration`] iterator, the deadline also
+//! accounts for the time spent running the operation. The [`ErrorKind`] of the returned [`Error`]
+//! tells whether the operation failed fatally, ran out of delays, or ran out of time.
+//!
+//! A try that hangs, such as a blocking read from a socket, would otherwise stall the retry loop
+//! forever. The [`retry_with_timeout`] function runs each try on its own thread and gives up on it
+//! after a time limit, retrying with a [`TimeoutError`]. [`retry_async_with_timeout`] does the
+//! same for asynchronous operations by racing each try against the [`AsyncSleeper`].
+//!
+//! To stop retrying early, such as during a graceful shutdown, use the [`retry_with_cancellation`]
+//! function with a [`CancellationToken`]. Cancelling the token from another thread wakes the retry
+//! loop immediately, even in the middle of a long delay.
+//!
+//! A [`Duration`] iterator cannot see why a try failed. To choose each delay based on the error,
+//! the duration of the failed try, or the time elapsed so far, implement [`Backoff`] and use it
+//! with [`Retry::with_backoff`]. Every [`Duration`] iterator is also a [`Backoff`].
+//!
+//! When a dependency is down, every caller would otherwise wait out its whole delay schedule. A
+//! [`CircuitBreaker`] shared between retry loops with [`retry_with_circuit_breaker`] counts failed
+//! tries, and once too many have failed makes retry loops stop with [`ErrorKind::CircuitOpen`]
+//! instead of trying again, until a cool-down has passed.
+//!
+//! During an incident, many independent retry loops can multiply the load on a struggling
+//! dependency. A [`RetryBudget`] shared between them with [`Retry::budget`] limits retries to
+//! a fraction of successful operations, and stops retrying with [`ErrorKind::BudgetExhausted`]
+//! once it is drained.
+//!
+//! For idempotent reads where latency matters more than load, the [`hedge`] and [`hedge_async`]
+//! functions start a speculative try in parallel whenever the previous one has not finished
+//! within a delay from the [`Duration`] iterator, and return whichever succeeds first.
+//!
+//! To observe each failed try, for example to log it or record metrics, use the
+//! [`retry_with_notify`] function. Its hook is called with a [`RetryEvent`] describing the error
+//! and the delay that is about to be waited before the next try.
+//!
+//! The options above can be combined with the [`Retry`] builder, which also offers a limit on the
+//! number of tries that is independent of the [`Duration`] iterator:
+//!
+//! ```
+//! # use std::time::Duration;
+//! # use retry::delay::Exponential;
+//! use retry::Retry;
+//!
+//! let result = Retry::new(Exponential::from_millis(10))
+//!     .max_tries(3)
+//!     .deadline(Duration::from_secs(1))
+//!     .notify(|event| eprintln!("try {} failed: {}", event.attempt, event.error))
+//!     .call(|| Err::<(), _>("always fails"));
+//!
+//! assert_eq!(result.unwrap_err().tries, 3);
+//! ```
+//!
 //! # Features
 //!
+//! - `metrics`: record the outcomes of retry loops, and export them in the Prometheus text format
 //! - `random`: offer some random delay utilities (on by default)
+//! - `serde`: deserialize a [`RetryPolicy`] from configuration files
+//! - `tokio`: offer asynchronous retries that wait using Tokio's timer
+//! - `tracing`: instrument synchronous retry loops with spans and events from the `tracing` crate
 
 #![deny(missing_debug_implementations, missing_docs, warnings)]
 
 use std::{
     error::Error as StdError,
     fmt::{Display, Error as FmtError, Formatter},
-    thread::sleep,
     time::Duration,
 };
 
+mod asynchronous;
+mod backoff;
+mod budget;
+mod builder;
+mod cancel;
+mod circuit;
+mod clock;
 pub mod delay;
+mod env;
+mod hedge;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod opresult;
+mod policy;
+mod timeout;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+mod trace;
 
+#[doc(inline)]
+pub use asynchronous::{
+    retry_async, retry_async_with_index, retry_async_with_options, AsyncSleeper, RetryAfterOptions,
+};
+#[doc(inline)]
+pub use backoff::{AttemptContext, Backoff};
+#[doc(inline)]
+pub use budget::{RetryBudget, RetryBudgetStats};
+#[doc(inline)]
+pub use builder::{Retry, RetryEvent};
+#[doc(inline)]
+pub use cancel::CancellationToken;
+#[doc(inline)]
+pub use circuit::{
+    retry_async_with_circuit_breaker, retry_with_circuit_breaker, CircuitBreaker, CircuitError,
+    CircuitState,
+};
+#[doc(inline)]
+pub use clock::{Clock, ManualClock, Sleeper, SystemClock};
+#[doc(inline)]
+pub use env::EnvPolicyError;
+#[doc(inline)]
+pub use hedge::{hedge, hedge_async};
 #[doc(inline)]
 pub use opresult::OperationResult;
+#[doc(inline)]
+pub use policy::{ParsePolicyError, PolicyDelays, PolicyOptions, RetryPolicy};
+#[doc(inline)]
+pub use timeout::{retry_async_with_timeout, retry_with_timeout, TimeoutError};
 
 /// Retry the given operation synchronously until it succeeds, or until the given [`Duration`]
 /// iterator ends.
@@ -152,54 +294,201 @@ where
 /// Retry the given operation synchronously until it succeeds, or until the given [`Duration`]
 /// iterator ends, with each iteration of the operation receiving the number of the attempt as an
 /// argument.
-pub fn retry_with_index<I, O, R, E, OR>(iterable: I, mut operation: O) -> Result<R, Error<E>>
+pub fn retry_with_index<I, O, R, E, OR>(iterable: I, operation: O) -> Result<R, Error<E>>
 where
     I: IntoIterator<Item = Duration>,
     O: FnMut(u64) -> OR,
     OR: Into<OperationResult<R, E>>,
 {
-    let mut iterator = iterable.into_iter();
-    let mut current_try = 1;
-    let mut total_delay = Duration::default();
-
-    loop {
-        match operation(current_try).into() {
-            OperationResult::Ok(value) => return Ok(value),
-            OperationResult::Retry(error) => {
-                if let Some(delay) = iterator.next() {
-                    sleep(delay);
-                    current_try += 1;
-                    total_delay += delay;
-                } else {
-                    return Err(Error {
-                        error,
-                        total_delay,
-                        tries: current_try,
-                    });
-                }
-            }
-            OperationResult::Err(error) => {
-                return Err(Error {
-                    error,
-                    total_delay,
-                    tries: current_try,
-                });
-            }
-        }
-    }
+    Retry::new(iterable).call_with_index(operation)
+}
+
+/// Retry the given operation synchronously until it succeeds, or until the given [`Duration`]
+/// iterator ends, waiting between tries with the given [`Sleeper`] instead of blocking the current
+/// thread with [`std::thread::sleep`].
+pub fn retry_with_sleeper<I, S, O, R, E, OR>(
+    iterable: I,
+    sleeper: S,
+    operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    S: Sleeper,
+    O: FnMut() -> OR,
+    OR: Into<OperationResult<R, E>>,
+{
+    Retry::new(iterable).sleeper(sleeper).call(operation)
+}
+
+/// Retry the given operation synchronously until it succeeds, until the given [`Duration`]
+/// iterator ends, or until the given deadline has passed since the first try.
+///
+/// The deadline accounts for the time spent running the operation as well as the time spent
+/// waiting between tries. No further tries are made once the deadline has passed or once the next
+/// delay would end after it, in which case the returned [`Error`] has the kind
+/// [`ErrorKind::DeadlineExceeded`].
+pub fn retry_with_deadline<I, O, R, E, OR>(
+    iterable: I,
+    deadline: Duration,
+    operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    O: FnMut() -> OR,
+    OR: Into<OperationResult<R, E>>,
+{
+    Retry::new(iterable).deadline(deadline).call(operation)
+}
+
+/// Retry the given operation synchronously until it succeeds, until the given [`Duration`]
+/// iterator ends, or until the given [`CancellationToken`] is cancelled.
+///
+/// Cancelling the token interrupts any wait between tries immediately. Once cancelled, no further
+/// tries are made and the returned [`Error`] has the kind [`ErrorKind::Cancelled`], along with the
+/// error from the last try. A try that is already running when the token is cancelled is allowed
+/// to finish, and its result is returned if it succeeds or fails fatally.
+pub fn retry_with_cancellation<I, O, R, E, OR>(
+    iterable: I,
+    cancellation: &CancellationToken,
+    operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    O: FnMut() -> OR,
+    OR: Into<OperationResult<R, E>>,
+{
+    Retry::new(iterable)
+        .cancellation(cancellation.clone())
+        .call(operation)
+}
+
+/// Retry the given operation synchronously until it succeeds, until the given [`Duration`]
+/// iterator ends, or until it returns an error that the given predicate rejects.
+///
+/// This is useful for operations that return a plain [`Result`], whose errors would otherwise
+/// always be retried: the predicate decides which errors are worth retrying, and any error for
+/// which it returns `false` is returned immediately with the kind [`ErrorKind::Fatal`].
+pub fn retry_if<I, P, O, R, E, OR>(iterable: I, predicate: P, operation: O) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    P: FnMut(&E) -> bool,
+    O: FnMut() -> OR,
+    OR: Into<OperationResult<R, E>>,
+{
+    Retry::new(iterable).when(predicate).call(operation)
+}
+
+/// Retry the given operation synchronously until it succeeds, or until the given [`Duration`]
+/// iterator ends, calling the given hook after each failed try that is about to be retried.
+///
+/// The hook receives a [`RetryEvent`] with the number of the failed try, its error, and the delay
+/// that is about to be waited before the next try, which makes it a convenient place for logging
+/// and metrics. It is not called for the last try, whose error is returned instead.
+pub fn retry_with_notify<I, N, O, R, E, OR>(
+    iterable: I,
+    notify: N,
+    operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    N: FnMut(&RetryEvent<'_, E>),
+    O: FnMut() -> OR,
+    OR: Into<OperationResult<R, E>>,
+{
+    Retry::new(iterable).notify(notify).call(operation)
 }
 
 /// An error with a retryable operation.
 #[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct Error<E> {
     /// The error returned by the operation on the last try.
     pub error: E,
     /// The duration spent waiting between retries of the operation.
     ///
-    /// Note that this does not include the time spent running the operation itself.
+    /// Note that this does not include the time spent running the operation itself, which is
+    /// [`Error::operation_time`].
     pub total_delay: Duration,
     /// The total number of times the operation was tried.
     pub tries: u64,
+    /// The time spent running the operation, summed over all tries.
+    ///
+    /// Tries that run in parallel, as with [`hedge`] and [`hedge_async`], are each counted in
+    /// full, so this can exceed [`Error::elapsed`].
+    pub operation_time: Duration,
+    /// The wall-clock time between the start of the first try and giving up, as measured by a
+    /// monotonic clock. When tries run one after another, this includes both
+    /// [`Error::total_delay`] and [`Error::operation_time`].
+    pub elapsed: Duration,
+    /// The reason no further tries were made.
+    pub kind: ErrorKind,
+    /// The number of tries that did not finish within the time limit given to
+    /// [`retry_with_timeout`] or [`retry_async_with_timeout`]. The remaining tries returned an
+    /// error from the operation.
+    pub timeouts: u64,
+    attempts: Vec<Attempt<E>>,
+}
+
+impl<E> Error<E> {
+    /// The tries that were retried before the last one, oldest first, if recording was enabled
+    /// with [`Retry::record_attempts`] or [`Retry::record_attempts_with_capacity`]. The error from
+    /// the last try is [`Error::error`].
+    #[must_use]
+    pub fn attempts(&self) -> &[Attempt<E>] {
+        &self.attempts
+    }
+}
+
+/// The successful result of a retryable operation, along with how it was reached, as returned by
+/// [`Retry::call_with_report`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Success<R> {
+    /// The value returned by the operation on the successful try.
+    pub value: R,
+    /// The total number of times the operation was tried, including the successful try.
+    pub tries: u64,
+    /// The duration spent waiting between retries of the operation.
+    pub total_delay: Duration,
+    /// The time spent running the operation, summed over all tries.
+    pub operation_time: Duration,
+    /// The wall-clock time between the start of the first try and the end of the successful try,
+    /// as measured by a monotonic clock.
+    pub elapsed: Duration,
+}
+
+/// A try of an operation that failed and was retried, as recorded in [`Error::attempts`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Attempt<E> {
+    /// The error returned by the operation on this try.
+    pub error: E,
+    /// The time between the start of the first try and the start of this try, as measured by the
+    /// retry loop's clock.
+    pub started_after: Duration,
+    /// The time spent running the operation on this try.
+    pub duration: Duration,
+    /// The delay waited after this try, before the next one.
+    pub delay: Duration,
+}
+
+/// The reason a retryable operation stopped being retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The operation returned [`OperationResult::Err`], or an error rejected by the predicate
+    /// given to [`retry_if`] or [`Retry::when`].
+    Fatal,
+    /// The [`Duration`] iterator ended.
+    Exhausted,
+    /// The deadline passed, or would have passed before the next try.
+    DeadlineExceeded,
+    /// The [`CancellationToken`] was cancelled.
+    Cancelled,
+    /// The [`CircuitBreaker`] was open, so no further tries were made.
+    CircuitOpen,
+    /// The [`RetryBudget`] had too few tokens left for another retry.
+    BudgetExhausted,
 }
 
 impl<E> Display for Error<E>
@@ -231,7 +520,11 @@ mod tests {
 
     use super::delay::{Exponential, Fixed, NoDelay};
     use super::opresult::OperationResult;
-    use super::{retry, retry_with_index, Error};
+    use super::{
+        retry, retry_if, retry_with_cancellation, retry_with_deadline, retry_with_index,
+        retry_with_notify, retry_with_sleeper, CancellationToken, Error, ErrorKind, ManualClock,
+        Retry,
+    };
 
     #[test]
     fn succeeds_with_infinite_retries() {
@@ -271,14 +564,11 @@ mod tests {
             None => Err("not 2"),
         });
 
-        assert_eq!(
-            res,
-            Err(Error {
-                error: "not 2",
-                tries: 2,
-                total_delay: Duration::from_millis(0)
-            })
-        );
+        let err = res.unwrap_err();
+        assert_eq!(err.error, "not 2");
+        assert_eq!(err.tries, 2);
+        assert_eq!(err.total_delay, Duration::from_millis(0));
+        assert_eq!(err.kind, ErrorKind::Exhausted);
     }
 
     #[test]
@@ -291,14 +581,11 @@ mod tests {
             None => OperationResult::Err("not 2"),
         });
 
-        assert_eq!(
-            res,
-            Err(Error {
-                error: "no retry",
-                tries: 1,
-                total_delay: Duration::from_millis(0)
-            })
-        );
+        let err = res.unwrap_err();
+        assert_eq!(err.error, "no retry");
+        assert_eq!(err.tries, 1);
+        assert_eq!(err.total_delay, Duration::from_millis(0));
+        assert_eq!(err.kind, ErrorKind::Fatal);
     }
 
     #[test]
@@ -386,4 +673,173 @@ mod tests {
 
         assert_eq!(value, 1);
     }
+
+    #[test]
+    fn sleeps_with_sleeper() {
+        let clock = ManualClock::new();
+        let mut collection = vec![1, 2, 3].into_iter();
+
+        let value = retry_with_sleeper(
+            Exponential::from_millis(1_000),
+            &clock,
+            || match collection.next() {
+                Some(n) if n == 3 => Ok(n),
+                Some(_) => Err("not 3"),
+                None => Err("not 3"),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(value, 3);
+        assert_eq!(
+            clock.sleeps(),
+            vec![Duration::from_secs(1), Duration::from_secs(2)]
+        );
+    }
+
+    #[test]
+    fn stops_at_deadline() {
+        let clock = ManualClock::new();
+
+        let res = Retry::new(Fixed::from_millis(100))
+            .deadline(Duration::from_millis(250))
+            .clock(&clock)
+            .sleeper(&clock)
+            .call(|| {
+                clock.advance(Duration::from_millis(10));
+                Err::<(), _>("fail")
+            });
+
+        assert_eq!(
+            res,
+            Err(Error {
+                error: "fail",
+                tries: 3,
+                total_delay: Duration::from_millis(200),
+                operation_time: Duration::from_millis(30),
+                elapsed: Duration::from_millis(230),
+                kind: ErrorKind::DeadlineExceeded,
+                timeouts: 0,
+                attempts: Vec::new(),
+            })
+        );
+        assert_eq!(
+            clock.sleeps(),
+            vec![Duration::from_millis(100), Duration::from_millis(100)]
+        );
+    }
+
+    #[test]
+    fn stops_when_deadline_has_passed() {
+        let clock = ManualClock::new();
+
+        let res = Retry::new(NoDelay)
+            .deadline(Duration::from_secs(1))
+            .clock(&clock)
+            .sleeper(&clock)
+            .call_with_index(|current_try| {
+                clock.advance(Duration::from_millis(400));
+                Err::<(), _>(current_try)
+            });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, 3);
+        assert_eq!(err.elapsed, Duration::from_millis(1_200));
+        assert_eq!(err.kind, ErrorKind::DeadlineExceeded);
+    }
+
+    #[test]
+    fn exhausted_before_deadline() {
+        let res = retry_with_deadline(NoDelay.take(2), Duration::from_secs(60), || {
+            Err::<(), _>("fail")
+        });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+    }
+
+    #[test]
+    fn cancellation_interrupts_delay() {
+        let token = CancellationToken::new();
+        let shutdown = token.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            shutdown.cancel();
+        });
+
+        let res =
+            retry_with_cancellation(Fixed::from_millis(60_000), &token, || Err::<(), _>("fail"));
+        handle.join().unwrap();
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, "fail");
+        assert_eq!(err.tries, 1);
+        assert!(err.total_delay < Duration::from_secs(60));
+        assert_eq!(err.kind, ErrorKind::Cancelled);
+    }
+
+    #[test]
+    fn cancellation_before_retry() {
+        let token = CancellationToken::new();
+
+        let res = retry_with_cancellation(NoDelay, &token, || {
+            token.cancel();
+            Err::<(), _>("fail")
+        });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.tries, 1);
+        assert_eq!(err.total_delay, Duration::default());
+        assert_eq!(err.kind, ErrorKind::Cancelled);
+    }
+
+    #[test]
+    fn notifies_before_each_retry() {
+        let mut events = Vec::new();
+
+        let res = retry_with_notify(
+            Exponential::from_millis(1).take(2),
+            |event| events.push((event.attempt, *event.error, event.delay, event.total_delay)),
+            || Err::<(), _>("fail"),
+        );
+
+        assert_eq!(res.unwrap_err().tries, 3);
+        assert_eq!(
+            events,
+            vec![
+                (
+                    1,
+                    "fail",
+                    Duration::from_millis(1),
+                    Duration::from_millis(0)
+                ),
+                (
+                    2,
+                    "fail",
+                    Duration::from_millis(2),
+                    Duration::from_millis(1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn retry_if_returns_rejected_errors() {
+        let mut collection = vec![1, 2, 3].into_iter();
+
+        let res = retry_if(
+            NoDelay,
+            |n: &i32| *n < 2,
+            || match collection.next() {
+                Some(n) => Err::<(), _>(n),
+                None => Err(0),
+            },
+        );
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, 2);
+        assert_eq!(err.tries, 2);
+        assert_eq!(err.kind, ErrorKind::Fatal);
+    }
 }
@@ -0,0 +1,385 @@
+//! Metrics for retry loops. (When the `metrics` Cargo feature is enabled.)
+//!
+//! A [`Recorder`] given to [`Retry::metrics`](crate::Retry::metrics) is told about every call of
+//! the retry loop once it finishes, under the operation name given alongside it. The
+//! [`InMemoryRecorder`] aggregates those calls per operation, and can render them in the
+//! Prometheus text exposition format with [`InMemoryRecorder::export_prometheus`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::sync::Arc;
+//! # use retry::delay::NoDelay;
+//! use retry::{metrics::InMemoryRecorder, Retry};
+//!
+//! let recorder = Arc::new(InMemoryRecorder::new());
+//!
+//! let _ = Retry::new(NoDelay.take(2))
+//!     .metrics("fetch_user", recorder.clone())
+//!     .call(|| Err::<(), _>("unavailable"));
+//!
+//! let metrics = recorder.operation("fetch_user").unwrap();
+//! assert_eq!(metrics.calls, 1);
+//! assert_eq!(metrics.give_ups(), 1);
+//! assert_eq!(metrics.tries.sum(), 3.0);
+//!
+//! assert!(recorder
+//!     .export_prometheus()
+//!     .contains("retry_calls_total{operation=\"fetch_user\",outcome=\"exhausted\"} 1"));
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::ErrorKind;
+
+const TRIES_BOUNDS: &[f64] = &[1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0];
+const SECONDS_BOUNDS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// A finished call of a retry loop, as reported to a [`Recorder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CallMetrics {
+    /// The total number of times the operation was tried.
+    pub tries: u64,
+    /// The duration spent waiting between tries.
+    pub total_delay: Duration,
+    /// The time spent running the operation, summed over all tries.
+    pub operation_time: Duration,
+    /// The time between the start of the first try and the end of the call.
+    pub elapsed: Duration,
+    /// The reason the call gave up, or `None` if the operation succeeded.
+    pub error_kind: Option<ErrorKind>,
+}
+
+impl CallMetrics {
+    /// The name of the outcome of the call, as used for the `outcome` label by
+    /// [`InMemoryRecorder::export_prometheus`]: `success`, or the snake-case name of the
+    /// [`ErrorKind`], such as `fatal` or `exhausted`.
+    #[must_use]
+    pub fn outcome(&self) -> &'static str {
+        match self.error_kind {
+            None => "success",
+            Some(ErrorKind::Fatal) => "fatal",
+            Some(ErrorKind::Exhausted) => "exhausted",
+            Some(ErrorKind::DeadlineExceeded) => "deadline_exceeded",
+            Some(ErrorKind::Cancelled) => "cancelled",
+            Some(ErrorKind::CircuitOpen) => "circuit_open",
+            Some(ErrorKind::BudgetExhausted) => "budget_exhausted",
+        }
+    }
+}
+
+/// A destination for the metrics of finished retry loops.
+pub trait Recorder: Send + Sync {
+    /// Record a finished call of the retry loop for the given operation.
+    fn record(&self, operation: &str, call: &CallMetrics);
+}
+
+/// A histogram of observed values, with cumulative buckets as in Prometheus.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(&mut self.counts) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// The number of observed values.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of the observed values.
+    #[must_use]
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// The upper bound of each bucket, along with the number of observed values that are less
+    /// than or equal to it. Values above the last bound are only included in
+    /// [`Histogram::count`].
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        self.bounds.iter().copied().zip(self.counts.iter().copied())
+    }
+}
+
+/// The aggregated metrics of one operation, as collected by an [`InMemoryRecorder`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct OperationMetrics {
+    /// The number of finished calls of the retry loop.
+    pub calls: u64,
+    /// The number of calls with each outcome, keyed by [`CallMetrics::outcome`].
+    pub outcomes: BTreeMap<&'static str, u64>,
+    /// The number of tries per call.
+    pub tries: Histogram,
+    /// The time spent waiting between tries per call, in seconds.
+    pub backoff_seconds: Histogram,
+}
+
+impl OperationMetrics {
+    fn new() -> Self {
+        OperationMetrics {
+            calls: 0,
+            outcomes: BTreeMap::new(),
+            tries: Histogram::new(TRIES_BOUNDS),
+            backoff_seconds: Histogram::new(SECONDS_BOUNDS),
+        }
+    }
+
+    /// The number of calls that succeeded.
+    #[must_use]
+    pub fn successes(&self) -> u64 {
+        self.outcomes.get("success").copied().unwrap_or(0)
+    }
+
+    /// The number of calls that stopped because of a fatal error.
+    #[must_use]
+    pub fn fatal_errors(&self) -> u64 {
+        self.outcomes.get("fatal").copied().unwrap_or(0)
+    }
+
+    /// The number of calls that gave up for any other reason, such as running out of delays or
+    /// passing the deadline.
+    #[must_use]
+    pub fn give_ups(&self) -> u64 {
+        self.calls - self.successes() - self.fatal_errors()
+    }
+}
+
+/// A [`Recorder`] that keeps the aggregated metrics of each operation in memory.
+#[derive(Debug, Default)]
+pub struct InMemoryRecorder {
+    operations: Mutex<BTreeMap<String, OperationMetrics>>,
+}
+
+impl InMemoryRecorder {
+    /// Create a new, empty [`InMemoryRecorder`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the metrics recorded so far for the given operation, if any calls of it have
+    /// finished.
+    #[must_use]
+    pub fn operation(&self, operation: &str) -> Option<OperationMetrics> {
+        self.operations().get(operation).cloned()
+    }
+
+    /// Render the metrics of every operation in the Prometheus text exposition format, ready to
+    /// be served from a `/metrics` endpoint.
+    ///
+    /// This produces the counter `retry_calls_total`, labelled by operation and outcome, and the
+    /// histograms `retry_tries` and `retry_backoff_seconds`, labelled by operation.
+    #[must_use]
+    pub fn export_prometheus(&self) -> String {
+        let operations = self.operations();
+        let mut output = String::new();
+
+        output.push_str("# HELP retry_calls_total Finished calls of a retry loop, by outcome.\n");
+        output.push_str("# TYPE retry_calls_total counter\n");
+        for (operation, metrics) in operations.iter() {
+            for (outcome, count) in &metrics.outcomes {
+                let _ = writeln!(
+                    output,
+                    "retry_calls_total{{operation=\"{}\",outcome=\"{}\"}} {}",
+                    escape(operation),
+                    outcome,
+                    count
+                );
+            }
+        }
+
+        write_histogram(
+            &mut output,
+            "retry_tries",
+            "Tries per call of a retry loop.",
+            operations
+                .iter()
+                .map(|(name, metrics)| (name, &metrics.tries)),
+        );
+        write_histogram(
+            &mut output,
+            "retry_backoff_seconds",
+            "Time spent waiting between tries per call of a retry loop.",
+            operations
+                .iter()
+                .map(|(name, metrics)| (name, &metrics.backoff_seconds)),
+        );
+
+        output
+    }
+
+    fn operations(&self) -> MutexGuard<'_, BTreeMap<String, OperationMetrics>> {
+        self.operations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Recorder for InMemoryRecorder {
+    fn record(&self, operation: &str, call: &CallMetrics) {
+        let mut operations = self.operations();
+        let metrics = operations
+            .entry(operation.to_owned())
+            .or_insert_with(OperationMetrics::new);
+
+        metrics.calls += 1;
+        *metrics.outcomes.entry(call.outcome()).or_insert(0) += 1;
+        metrics.tries.observe(call.tries as f64);
+        metrics
+            .backoff_seconds
+            .observe(call.total_delay.as_secs_f64());
+    }
+}
+
+fn write_histogram<'a>(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    histograms: impl Iterator<Item = (&'a String, &'a Histogram)>,
+) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} histogram", name);
+
+    for (operation, histogram) in histograms {
+        let operation = escape(operation);
+
+        for (bound, count) in histogram.buckets() {
+            let _ = writeln!(
+                output,
+                "{}_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                name, operation, bound, count
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "{}_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+            name,
+            operation,
+            histogram.count()
+        );
+        let _ = writeln!(
+            output,
+            "{}_sum{{operation=\"{}\"}} {}",
+            name,
+            operation,
+            histogram.sum()
+        );
+        let _ = writeln!(
+            output,
+            "{}_count{{operation=\"{}\"}} {}",
+            name,
+            operation,
+            histogram.count()
+        );
+    }
+}
+
+/// Escape a label value for the Prometheus text exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{CallMetrics, InMemoryRecorder, Recorder};
+    use crate::delay::{Fixed, NoDelay};
+    use crate::{ErrorKind, ManualClock, OperationResult, Retry};
+
+    #[test]
+    fn records_outcomes_per_operation() {
+        let recorder = Arc::new(InMemoryRecorder::new());
+        let clock = ManualClock::new();
+
+        let mut collection = vec![Err("a"), Ok(1)].into_iter();
+        let _ = Retry::new(Fixed::from_millis(100))
+            .sleeper(&clock)
+            .metrics("read", recorder.clone())
+            .call(|| collection.next().unwrap());
+        let _ = Retry::new(NoDelay)
+            .metrics("read", recorder.clone())
+            .call(|| OperationResult::<(), _>::Err("fatal"));
+        let shared: Arc<dyn Recorder> = recorder.clone();
+        let _ = Retry::new(NoDelay.take(1))
+            .metrics("write", shared)
+            .call(|| Err::<(), _>("fail"));
+
+        let read = recorder.operation("read").unwrap();
+        assert_eq!(read.calls, 2);
+        assert_eq!(read.successes(), 1);
+        assert_eq!(read.fatal_errors(), 1);
+        assert_eq!(read.give_ups(), 0);
+        assert_eq!(read.tries.sum(), 3.0);
+        assert_eq!(read.backoff_seconds.sum(), 0.1);
+
+        let write = recorder.operation("write").unwrap();
+        assert_eq!(write.give_ups(), 1);
+        assert_eq!(write.outcomes.get("exhausted"), Some(&1));
+
+        assert!(recorder.operation("delete").is_none());
+    }
+
+    #[test]
+    fn exports_prometheus_text() {
+        let recorder = InMemoryRecorder::new();
+        recorder.record(
+            "say \"hi\"",
+            &CallMetrics {
+                tries: 2,
+                total_delay: Duration::from_millis(20),
+                operation_time: Duration::default(),
+                elapsed: Duration::from_millis(20),
+                error_kind: Some(ErrorKind::DeadlineExceeded),
+            },
+        );
+
+        let text = recorder.export_prometheus();
+
+        assert!(text.contains("# TYPE retry_calls_total counter\n"));
+        assert!(text.contains(
+            "retry_calls_total{operation=\"say \\\"hi\\\"\",outcome=\"deadline_exceeded\"} 1\n"
+        ));
+        assert!(text.contains("retry_tries_bucket{operation=\"say \\\"hi\\\"\",le=\"1\"} 0\n"));
+        assert!(text.contains("retry_tries_bucket{operation=\"say \\\"hi\\\"\",le=\"2\"} 1\n"));
+        assert!(text.contains("retry_tries_bucket{operation=\"say \\\"hi\\\"\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("retry_backoff_seconds_sum{operation=\"say \\\"hi\\\"\"} 0.02\n"));
+        assert!(text.contains("retry_backoff_seconds_count{operation=\"say \\\"hi\\\"\"} 1\n"));
+    }
+}
@@ -18,14 +18,21 @@
 //! assert_eq!(value, 2);
 //! ```
 
+use std::time::Duration;
+
 /// A result that represents either success, retryable failure, or immediately-returning failure.
 #[must_use]
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
+#[non_exhaustive]
 pub enum OperationResult<T, E> {
     /// Contains the success value.
     Ok(T),
     /// Contains the error value if duration is exceeded.
     Retry(E),
+    /// Contains the error value if duration is exceeded, along with how long to wait before the
+    /// next try, such as a delay requested by a server. The delay is used in place of the next
+    /// delay from the [`Duration`] iterator.
+    RetryAfter(E, Duration),
     /// Contains an error value to return immediately.
     Err(E),
 }
@@ -86,6 +93,28 @@ impl<T, E> OperationResult<T, E> {
         matches!(self, Self::Retry(_))
     }
 
+    /// Returns `true` if the result is [`OperationResult::RetryAfter`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use retry::OperationResult;
+    ///
+    /// let x: OperationResult<i32, &str> = OperationResult::Retry("Some error message");
+    /// assert_eq!(x.is_retry_after(), false);
+    ///
+    /// let x: OperationResult<i32, &str> =
+    ///     OperationResult::RetryAfter("Some error message", Duration::from_secs(1));
+    /// assert_eq!(x.is_retry_after(), true);
+    /// ```
+    #[must_use]
+    pub const fn is_retry_after(&self) -> bool {
+        matches!(self, Self::RetryAfter(..))
+    }
+
     /// Returns `true` if the result is [`OperationResult::Err`].
     ///
     /// # Examples
@@ -108,4 +137,14 @@ impl<T, E> OperationResult<T, E> {
     pub const fn is_err(&self) -> bool {
         matches!(self, Self::Err(_))
     }
+
+    /// Apply the given function to the error, keeping the variant.
+    pub(crate) fn map_err<F>(self, op: impl FnOnce(E) -> F) -> OperationResult<T, F> {
+        match self {
+            Self::Ok(value) => OperationResult::Ok(value),
+            Self::Retry(error) => OperationResult::Retry(op(error)),
+            Self::RetryAfter(error, delay) => OperationResult::RetryAfter(op(error), delay),
+            Self::Err(error) => OperationResult::Err(op(error)),
+        }
+    }
 }
@@ -0,0 +1,1439 @@
+//! Retry policies described by data, such as configuration files.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! use retry::{retry, PolicyOptions, RetryPolicy};
+//!
+//! let mut options = PolicyOptions::default();
+//! options.max_tries = Some(3);
+//!
+//! let policy = RetryPolicy::Exponential {
+//!     base: Duration::from_millis(10),
+//!     factor: 2.0,
+//!     options,
+//! };
+//!
+//! let result = retry(&policy, || Err::<(), _>("fail"));
+//!
+//! assert_eq!(result.unwrap_err().tries, 3);
+//! ```
+//!
+//! With the `serde` Cargo feature enabled, a [`RetryPolicy`] can be deserialized from any format
+//! supported by Serde. The strategy is named by the `strategy` field, and durations are written
+//! as a number followed by a unit, such as `"250ms"` or `"1.5s"`:
+//!
+//! ```rust
+//! # #[cfg(feature = "serde")]
+//! # fn main() -> Result<(), serde_json::Error> {
+//! # use std::time::Duration;
+//! use retry::RetryPolicy;
+//!
+//! let policy: RetryPolicy = serde_json::from_str(
+//!     r#"{ "strategy": "fibonacci", "base": "100ms", "max_tries": 5, "max_total_delay": "30s" }"#,
+//! )?;
+//!
+//! assert_eq!(policy.options().max_total_delay, Some(Duration::from_secs(30)));
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "serde"))]
+//! # fn main() {}
+//! ```
+//!
+//! A policy can also be parsed from, and displayed as, a compact string, which suits command-line
+//! flags and environment variables:
+//!
+//! ```rust
+//! use retry::RetryPolicy;
+//!
+//! let policy: RetryPolicy = "fibonacci(50ms)*5".parse().unwrap();
+//!
+//! assert_eq!(policy.options().max_tries, Some(6));
+//! assert_eq!(policy.to_string(), "fibonacci(50ms, tries=6)");
+//! ```
+
+use std::{
+    convert::TryFrom,
+    error::Error as StdError,
+    fmt::{Debug, Display, Error as FmtError, Formatter},
+    str::FromStr,
+    time::Duration,
+};
+
+#[cfg(feature = "random")]
+use rand::{rngs::StdRng, SeedableRng};
+#[cfg(feature = "serde")]
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize};
+
+#[cfg(feature = "random")]
+use crate::delay::{jitter, Range};
+use crate::delay::{Exponential, Fibonacci, Fixed, NoDelay};
+
+/// A delay strategy and the limits applied to it, which can be turned into a [`Duration`]
+/// iterator with [`RetryPolicy::delays`] or passed directly to [`retry`](crate::retry).
+///
+/// The enum is non-exhaustive, since the strategies available depend on the enabled Cargo
+/// features.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "strategy", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum RetryPolicy {
+    /// Retry immediately, as with [`NoDelay`].
+    NoDelay {
+        /// The limits applied to the strategy.
+        #[cfg_attr(feature = "serde", serde(flatten))]
+        options: PolicyOptions,
+    },
+    /// Wait the same delay before every retry, as with [`Fixed`].
+    Fixed {
+        /// The delay before every retry.
+        #[cfg_attr(feature = "serde", serde(with = "duration"))]
+        delay: Duration,
+        /// The limits applied to the strategy.
+        #[cfg_attr(feature = "serde", serde(flatten))]
+        options: PolicyOptions,
+    },
+    /// Multiply the delay by a factor after every retry, as with [`Exponential`].
+    Exponential {
+        /// The delay before the first retry.
+        #[cfg_attr(feature = "serde", serde(with = "duration"))]
+        base: Duration,
+        /// The factor the delay is multiplied by after every retry, `2.0` if omitted.
+        factor: f64,
+        /// The limits applied to the strategy.
+        #[cfg_attr(feature = "serde", serde(flatten))]
+        options: PolicyOptions,
+    },
+    /// Make each delay the sum of the previous two, as with [`Fibonacci`].
+    Fibonacci {
+        /// The delay before the first and second retries.
+        #[cfg_attr(feature = "serde", serde(with = "duration"))]
+        base: Duration,
+        /// The limits applied to the strategy.
+        #[cfg_attr(feature = "serde", serde(flatten))]
+        options: PolicyOptions,
+    },
+    /// Choose each delay at random between a minimum and a maximum, inclusive, as with
+    /// [`Range`]. (When the `random` Cargo feature is enabled.)
+    #[cfg(feature = "random")]
+    Range {
+        /// The shortest possible delay.
+        #[cfg_attr(feature = "serde", serde(with = "duration"))]
+        min: Duration,
+        /// The longest possible delay.
+        #[cfg_attr(feature = "serde", serde(with = "duration"))]
+        max: Duration,
+        /// The limits applied to the strategy.
+        #[cfg_attr(feature = "serde", serde(flatten))]
+        options: PolicyOptions,
+    },
+}
+
+/// Limits applied to the delays of a [`RetryPolicy`], whatever its strategy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct PolicyOptions {
+    /// Apply full random jitter to each delay, as with [`jitter`]. (When the `random` Cargo
+    /// feature is enabled.)
+    #[cfg(feature = "random")]
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_false"))]
+    pub jitter: bool,
+    /// The most times the operation is tried, including the first try.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub max_tries: Option<u64>,
+    /// The longest delay between tries. Longer delays from the strategy are shortened to it.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "duration::option"
+        )
+    )]
+    pub max_delay: Option<Duration>,
+    /// The most time spent waiting between tries. Retrying stops when the next delay would take
+    /// the total delay past it.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "duration::option"
+        )
+    )]
+    pub max_total_delay: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// The limits applied to the strategy.
+    #[must_use]
+    pub fn options(&self) -> &PolicyOptions {
+        match self {
+            RetryPolicy::NoDelay { options }
+            | RetryPolicy::Fixed { options, .. }
+            | RetryPolicy::Exponential { options, .. }
+            | RetryPolicy::Fibonacci { options, .. } => options,
+            #[cfg(feature = "random")]
+            RetryPolicy::Range { options, .. } => options,
+        }
+    }
+
+    /// The limits applied to the strategy, for modification.
+    pub fn options_mut(&mut self) -> &mut PolicyOptions {
+        match self {
+            RetryPolicy::NoDelay { options }
+            | RetryPolicy::Fixed { options, .. }
+            | RetryPolicy::Exponential { options, .. }
+            | RetryPolicy::Fibonacci { options, .. } => options,
+            #[cfg(feature = "random")]
+            RetryPolicy::Range { options, .. } => options,
+        }
+    }
+
+    /// The name of the strategy, as used in policy strings.
+    pub(crate) fn strategy(&self) -> &'static str {
+        match self {
+            RetryPolicy::NoDelay { .. } => "no_delay",
+            RetryPolicy::Fixed { .. } => "fixed",
+            RetryPolicy::Exponential { .. } => "exponential",
+            RetryPolicy::Fibonacci { .. } => "fibonacci",
+            #[cfg(feature = "random")]
+            RetryPolicy::Range { .. } => "range",
+        }
+    }
+
+    /// The first delay of the strategy, if it has a single one.
+    pub(crate) fn base(&self) -> Option<Duration> {
+        match *self {
+            RetryPolicy::Fixed { delay: base, .. }
+            | RetryPolicy::Exponential { base, .. }
+            | RetryPolicy::Fibonacci { base, .. } => Some(base),
+            _ => None,
+        }
+    }
+
+    /// Check the parts of the policy that parsing and deserializing reject, returning a
+    /// description of the first problem found.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match *self {
+            RetryPolicy::Exponential { base, factor, .. } => {
+                whole_millis("exponential", base)?;
+                valid_factor(factor)
+            }
+            RetryPolicy::Fibonacci { base, .. } => whole_millis("fibonacci", base),
+            #[cfg(feature = "random")]
+            RetryPolicy::Range { min, max, .. } => {
+                whole_millis("range", min)?;
+                whole_millis("range", max)?;
+                valid_range(min, max)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Create the [`Duration`] iterator described by this policy.
+    ///
+    /// Like the types in the [`delay`](crate::delay) module, the `exponential`, `fibonacci` and
+    /// `range` strategies count delays in whole milliseconds, and discard any smaller part.
+    /// Policies that are parsed or deserialized are rejected if they have one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the factor of an `exponential` policy is not a finite number greater than zero,
+    /// or if the minimum of a `range` policy is greater than its maximum. Parsing and
+    /// deserializing reject such policies.
+    #[must_use]
+    pub fn delays(&self) -> PolicyDelays {
+        let delays: Box<dyn Iterator<Item = Duration> + Send> = match *self {
+            RetryPolicy::NoDelay { .. } => Box::new(NoDelay),
+            RetryPolicy::Fixed { delay, .. } => Box::new(Fixed::from(delay)),
+            RetryPolicy::Exponential { base, factor, .. } => {
+                if let Err(message) = valid_factor(factor) {
+                    panic!("{}", message);
+                }
+
+                Box::new(Exponential::from_millis_with_factor(
+                    base.as_millis() as u64,
+                    factor,
+                ))
+            }
+            RetryPolicy::Fibonacci { base, .. } => Box::new(Fibonacci::from(base)),
+            #[cfg(feature = "random")]
+            RetryPolicy::Range { min, max, .. } => {
+                Box::new(Range::from(min..=max).with_rng(StdRng::from_rng(&mut rand::rng())))
+            }
+        };
+
+        PolicyDelays {
+            delays,
+            options: *self.options(),
+            retries: 0,
+            total_delay: Duration::default(),
+        }
+    }
+}
+
+impl IntoIterator for RetryPolicy {
+    type Item = Duration;
+    type IntoIter = PolicyDelays;
+
+    fn into_iter(self) -> PolicyDelays {
+        self.delays()
+    }
+}
+
+impl IntoIterator for &RetryPolicy {
+    type Item = Duration;
+    type IntoIter = PolicyDelays;
+
+    fn into_iter(self) -> PolicyDelays {
+        self.delays()
+    }
+}
+
+/// The [`Duration`] iterator described by a [`RetryPolicy`], created with
+/// [`RetryPolicy::delays`].
+pub struct PolicyDelays {
+    delays: Box<dyn Iterator<Item = Duration> + Send>,
+    options: PolicyOptions,
+    retries: u64,
+    total_delay: Duration,
+}
+
+impl Iterator for PolicyDelays {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        if self
+            .options
+            .max_tries
+            .is_some_and(|max_tries| self.retries + 1 >= max_tries)
+        {
+            return None;
+        }
+
+        let mut delay = self.delays.next()?;
+
+        if let Some(max_delay) = self.options.max_delay {
+            delay = delay.min(max_delay);
+        }
+
+        #[cfg(feature = "random")]
+        if self.options.jitter {
+            delay = jitter(delay);
+        }
+
+        if self.options.max_total_delay.is_some_and(|max_total_delay| {
+            self.total_delay
+                .checked_add(delay)
+                .is_none_or(|total| total > max_total_delay)
+        }) {
+            return None;
+        }
+
+        self.retries += 1;
+        self.total_delay = self.total_delay.saturating_add(delay);
+        Some(delay)
+    }
+}
+
+impl Debug for PolicyDelays {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+        formatter
+            .debug_struct("PolicyDelays")
+            .field("options", &self.options)
+            .field("retries", &self.retries)
+            .field("total_delay", &self.total_delay)
+            .finish_non_exhaustive()
+    }
+}
+
+const UNITS: &[(&str, u128)] = &[
+    ("h", 3_600_000_000_000),
+    ("m", 60_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("ns", 1),
+];
+
+/// Parse a duration written as a number followed by a unit, such as `250ms` or `1.5s`.
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let unit = match unit.trim_start() {
+        "µs" => "us",
+        unit => unit,
+    };
+    let (_, nanos) = UNITS.iter().find(|(name, _)| *name == unit)?;
+
+    let nanos = match number.split_once('.') {
+        None => number.parse::<u128>().ok()?.checked_mul(*nanos)?,
+        Some((whole, fraction)) => {
+            if whole.is_empty() && fraction.is_empty() {
+                return None;
+            }
+            let number: f64 = number.parse().ok()?;
+            (number * *nanos as f64).round() as u128
+        }
+    };
+
+    let secs = u64::try_from(nanos / 1_000_000_000).ok()?;
+    Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// Format a duration in the largest unit that represents it exactly, such as `250ms` or `2m`.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+    if nanos == 0 {
+        return "0s".to_owned();
+    }
+
+    let (name, unit) = UNITS
+        .iter()
+        .find(|(_, unit)| nanos.is_multiple_of(*unit))
+        .expect("every duration is a whole number of nanoseconds");
+    format!("{}{}", nanos / unit, name)
+}
+
+/// An error from parsing a [`RetryPolicy`] from a string, such as an unknown strategy or a
+/// malformed duration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsePolicyError {
+    position: usize,
+    message: String,
+}
+
+impl ParsePolicyError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        ParsePolicyError {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// The byte offset in the parsed string at which the error was found.
+    #[must_use]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl Display for ParsePolicyError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+        write!(formatter, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl StdError for ParsePolicyError {}
+
+/// A cursor over a policy string, which splits it into words and punctuation.
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume the given punctuation if it comes next.
+    fn eat(&mut self, punctuation: char) -> bool {
+        self.skip_whitespace();
+        if self.text[self.position..].starts_with(punctuation) {
+            self.position += punctuation.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the next word, made of letters, digits, underscores and dots, returning it along
+    /// with its position.
+    fn word(&mut self, expected: &str) -> Result<(usize, &'a str), ParsePolicyError> {
+        self.skip_whitespace();
+        let start = self.position;
+        let rest = &self.text[start..];
+        let length = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(rest.len());
+
+        if length == 0 {
+            return Err(self.unexpected(expected));
+        }
+
+        self.position += length;
+        Ok((start, &rest[..length]))
+    }
+
+    /// An error for whatever comes next, which is not what was expected.
+    fn unexpected(&self, expected: &str) -> ParsePolicyError {
+        match self.text[self.position..].chars().next() {
+            Some(found) => ParsePolicyError::new(
+                self.position,
+                format!("expected {}, found `{}`", expected, found),
+            ),
+            None => ParsePolicyError::new(
+                self.position,
+                format!("expected {}, found end of string", expected),
+            ),
+        }
+    }
+}
+
+/// Check that a delay of the given strategy has no part smaller than a millisecond, which the
+/// delay types in the [`delay`](crate::delay) module would discard.
+fn whole_millis(strategy: &str, duration: Duration) -> Result<(), String> {
+    if duration.subsec_nanos().is_multiple_of(1_000_000) {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` delays must be whole milliseconds, found {}",
+            strategy,
+            format_duration(duration)
+        ))
+    }
+}
+
+/// Check that the factor of an `exponential` policy is a finite number greater than zero, which
+/// is also what keeps it readable by [`RetryPolicy::from_str`] once displayed.
+pub(crate) fn valid_factor(factor: f64) -> Result<(), String> {
+    if factor.is_finite() && factor > 0.0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "the factor of `exponential` must be a finite number greater than zero, found {}",
+            factor
+        ))
+    }
+}
+
+/// Check that the minimum of a `range` policy is not greater than its maximum.
+#[cfg(feature = "random")]
+fn valid_range(min: Duration, max: Duration) -> Result<(), String> {
+    if min <= max {
+        Ok(())
+    } else {
+        Err(format!(
+            "the minimum of a range, {}, is greater than its maximum, {}",
+            format_duration(min),
+            format_duration(max)
+        ))
+    }
+}
+
+fn parse_duration_at(position: usize, text: &str) -> Result<Duration, ParsePolicyError> {
+    parse_duration(text).ok_or_else(|| {
+        ParsePolicyError::new(
+            position,
+            format!(
+                "invalid duration `{}`, expected a number followed by a unit such as `250ms`",
+                text
+            ),
+        )
+    })
+}
+
+fn parse_number_at<T: FromStr>(
+    position: usize,
+    text: &str,
+    expected: &str,
+) -> Result<T, ParsePolicyError> {
+    text.parse()
+        .map_err(|_| ParsePolicyError::new(position, format!("invalid {} `{}`", expected, text)))
+}
+
+impl FromStr for RetryPolicy {
+    type Err = ParsePolicyError;
+
+    /// Parse a policy written as a strategy followed by its arguments in parentheses, such as
+    /// `exponential(100ms, factor=2, max=10s, jitter=full, tries=8)`.
+    ///
+    /// The strategies are `no_delay`, `fixed(delay)`, `exponential(base)`, `fibonacci(base)` and
+    /// `range(min, max)`. After the positional arguments, the options `factor` (for
+    /// `exponential`), `max`, `max_total`, `tries` and `jitter` (either `full` or `none`) may be
+    /// given by name. A trailing `*n`, as in `fibonacci(50ms)*5`, limits the policy to `n`
+    /// retries, like [`Iterator::take`].
+    fn from_str(text: &str) -> Result<Self, ParsePolicyError> {
+        let mut parser = Parser { text, position: 0 };
+        let (name_position, name) = parser.word("a strategy name")?;
+
+        let mut positional = Vec::new();
+        let mut factor = None;
+        let mut options = PolicyOptions::default();
+        let mut named = Vec::new();
+
+        if parser.eat('(') && !parser.eat(')') {
+            loop {
+                let (position, word) = parser.word("an argument")?;
+
+                if !parser.eat('=') {
+                    if !named.is_empty() {
+                        return Err(ParsePolicyError::new(
+                            position,
+                            "positional arguments must come before named options",
+                        ));
+                    }
+                    positional.push((position, word));
+                } else {
+                    if named.contains(&word) {
+                        return Err(ParsePolicyError::new(
+                            position,
+                            format!("option `{}` is given more than once", word),
+                        ));
+                    }
+                    named.push(word);
+
+                    let (value_position, value) = parser.word("a value")?;
+                    match word {
+                        "factor" if name == "exponential" => {
+                            let value = parse_number_at(value_position, value, "factor")?;
+                            valid_factor(value).map_err(|message| {
+                                ParsePolicyError::new(value_position, message)
+                            })?;
+                            factor = Some(value);
+                        }
+                        "max" => {
+                            options.max_delay = Some(parse_duration_at(value_position, value)?);
+                        }
+                        "max_total" => {
+                            options.max_total_delay =
+                                Some(parse_duration_at(value_position, value)?);
+                        }
+                        "tries" => {
+                            options.max_tries =
+                                Some(parse_number_at(value_position, value, "number of tries")?);
+                        }
+                        #[cfg(feature = "random")]
+                        "jitter" => {
+                            options.jitter = match value {
+                                "full" => true,
+                                "none" => false,
+                                _ => {
+                                    return Err(ParsePolicyError::new(
+                                        value_position,
+                                        format!(
+                                            "invalid jitter `{}`, expected `full` or `none`",
+                                            value
+                                        ),
+                                    ))
+                                }
+                            };
+                        }
+                        _ => {
+                            return Err(ParsePolicyError::new(
+                                position,
+                                format!("unknown option `{}` for `{}`", word, name),
+                            ))
+                        }
+                    }
+                }
+
+                if parser.eat(')') {
+                    break;
+                }
+                if !parser.eat(',') {
+                    return Err(parser.unexpected("`,` or `)`"));
+                }
+            }
+        }
+
+        if parser.eat('*') {
+            let (position, count) = parser.word("a number of retries")?;
+            if named.contains(&"tries") {
+                return Err(ParsePolicyError::new(
+                    position,
+                    "`*` cannot be combined with the `tries` option",
+                ));
+            }
+
+            let retries: u64 = parse_number_at(position, count, "number of retries")?;
+            options.max_tries = Some(retries.saturating_add(1));
+        }
+
+        parser.skip_whitespace();
+        if parser.position < text.len() {
+            return Err(parser.unexpected("end of string"));
+        }
+
+        let arity = match name {
+            "no_delay" => 0,
+            "fixed" | "exponential" | "fibonacci" => 1,
+            #[cfg(feature = "random")]
+            "range" => 2,
+            _ => {
+                return Err(ParsePolicyError::new(
+                    name_position,
+                    format!("unknown strategy `{}`", name),
+                ))
+            }
+        };
+
+        if positional.len() != arity {
+            let position = positional
+                .get(arity)
+                .map_or(name_position, |(position, _)| *position);
+            return Err(ParsePolicyError::new(
+                position,
+                format!(
+                    "`{}` takes {} positional argument{}, found {}",
+                    name,
+                    arity,
+                    if arity == 1 { "" } else { "s" },
+                    positional.len()
+                ),
+            ));
+        }
+
+        #[cfg(feature = "random")]
+        let last_position = positional
+            .last()
+            .map_or(name_position, |(position, _)| *position);
+        let mut durations = positional.into_iter().map(|(position, text)| {
+            let duration = parse_duration_at(position, text)?;
+            if name != "fixed" {
+                whole_millis(name, duration)
+                    .map_err(|message| ParsePolicyError::new(position, message))?;
+            }
+
+            Ok(duration)
+        });
+
+        Ok(match name {
+            "no_delay" => RetryPolicy::NoDelay { options },
+            "fixed" => RetryPolicy::Fixed {
+                delay: durations.next().expect("arity was checked")?,
+                options,
+            },
+            "exponential" => RetryPolicy::Exponential {
+                base: durations.next().expect("arity was checked")?,
+                factor: factor.unwrap_or(2.0),
+                options,
+            },
+            "fibonacci" => RetryPolicy::Fibonacci {
+                base: durations.next().expect("arity was checked")?,
+                options,
+            },
+            #[cfg(feature = "random")]
+            "range" => {
+                let min = durations.next().expect("arity was checked")?;
+                let max = durations.next().expect("arity was checked")?;
+                valid_range(min, max)
+                    .map_err(|message| ParsePolicyError::new(last_position, message))?;
+
+                RetryPolicy::Range { min, max, options }
+            }
+            _ => unreachable!("strategy was checked"),
+        })
+    }
+}
+
+impl Display for RetryPolicy {
+    /// Format the policy in the form parsed by [`RetryPolicy::from_str`], so that it can be
+    /// logged and parsed back.
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+        let mut arguments = Vec::new();
+
+        match self {
+            RetryPolicy::NoDelay { .. } => {}
+            RetryPolicy::Fixed { delay, .. } => arguments.push(format_duration(*delay)),
+            RetryPolicy::Exponential { base, factor, .. } => {
+                arguments.push(format_duration(*base));
+                if *factor != 2.0 {
+                    arguments.push(format!("factor={}", factor));
+                }
+            }
+            RetryPolicy::Fibonacci { base, .. } => arguments.push(format_duration(*base)),
+            #[cfg(feature = "random")]
+            RetryPolicy::Range { min, max, .. } => {
+                arguments.push(format_duration(*min));
+                arguments.push(format_duration(*max));
+            }
+        }
+
+        let options = self.options();
+        if let Some(max_delay) = options.max_delay {
+            arguments.push(format!("max={}", format_duration(max_delay)));
+        }
+        #[cfg(feature = "random")]
+        if options.jitter {
+            arguments.push("jitter=full".to_owned());
+        }
+        if let Some(max_tries) = options.max_tries {
+            arguments.push(format!("tries={}", max_tries));
+        }
+        if let Some(max_total_delay) = options.max_total_delay {
+            arguments.push(format!("max_total={}", format_duration(max_total_delay)));
+        }
+
+        formatter.write_str(self.strategy())?;
+        if !arguments.is_empty() {
+            write!(formatter, "({})", arguments.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The fields of a serialized [`RetryPolicy`], whatever its strategy.
+///
+/// The serialized policy flattens its [`PolicyOptions`] into the strategy's fields, and serde
+/// cannot reject unknown fields of a flattened struct, so the policy is read through this struct
+/// instead. A misspelled limit is then an error rather than silently no limit at all.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFields {
+    strategy: Strategy,
+    #[serde(default, with = "duration::option")]
+    delay: Option<Duration>,
+    #[serde(default, with = "duration::option")]
+    base: Option<Duration>,
+    #[serde(default)]
+    factor: Option<f64>,
+    #[cfg(feature = "random")]
+    #[serde(default, with = "duration::option")]
+    min: Option<Duration>,
+    #[cfg(feature = "random")]
+    #[serde(default, with = "duration::option")]
+    max: Option<Duration>,
+    #[cfg(feature = "random")]
+    #[serde(default)]
+    jitter: bool,
+    #[serde(default)]
+    max_tries: Option<u64>,
+    #[serde(default, with = "duration::option")]
+    max_delay: Option<Duration>,
+    #[serde(default, with = "duration::option")]
+    max_total_delay: Option<Duration>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Strategy {
+    NoDelay,
+    Fixed,
+    Exponential,
+    Fibonacci,
+    #[cfg(feature = "random")]
+    Range,
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for RetryPolicy {
+    /// Deserialize a policy, rejecting unknown fields, fields of other strategies, and any policy
+    /// that [`RetryPolicy::delays`] could not turn into delays.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = PolicyFields::deserialize(deserializer)?;
+        let options = PolicyOptions {
+            #[cfg(feature = "random")]
+            jitter: fields.jitter,
+            max_tries: fields.max_tries,
+            max_delay: fields.max_delay,
+            max_total_delay: fields.max_total_delay,
+        };
+        let required =
+            |value: Option<Duration>, field| value.ok_or_else(|| D::Error::missing_field(field));
+
+        let policy = match fields.strategy {
+            Strategy::NoDelay => RetryPolicy::NoDelay { options },
+            Strategy::Fixed => RetryPolicy::Fixed {
+                delay: required(fields.delay, "delay")?,
+                options,
+            },
+            Strategy::Exponential => RetryPolicy::Exponential {
+                base: required(fields.base, "base")?,
+                factor: fields.factor.unwrap_or(2.0),
+                options,
+            },
+            Strategy::Fibonacci => RetryPolicy::Fibonacci {
+                base: required(fields.base, "base")?,
+                options,
+            },
+            #[cfg(feature = "random")]
+            Strategy::Range => RetryPolicy::Range {
+                min: required(fields.min, "min")?,
+                max: required(fields.max, "max")?,
+                options,
+            },
+        };
+
+        let given = [
+            ("delay", fields.delay.is_some()),
+            ("base", fields.base.is_some()),
+            ("factor", fields.factor.is_some()),
+            #[cfg(feature = "random")]
+            ("min", fields.min.is_some()),
+            #[cfg(feature = "random")]
+            ("max", fields.max.is_some()),
+        ];
+        let applicable: &[&str] = match policy {
+            RetryPolicy::NoDelay { .. } => &[],
+            RetryPolicy::Fixed { .. } => &["delay"],
+            RetryPolicy::Exponential { .. } => &["base", "factor"],
+            RetryPolicy::Fibonacci { .. } => &["base"],
+            #[cfg(feature = "random")]
+            RetryPolicy::Range { .. } => &["min", "max"],
+        };
+        if let Some((field, _)) = given
+            .iter()
+            .find(|(field, given)| *given && !applicable.contains(field))
+        {
+            return Err(D::Error::custom(format_args!(
+                "`{}` does not apply to the `{}` strategy",
+                field,
+                policy.strategy()
+            )));
+        }
+
+        policy.validate().map_err(DeError::custom)?;
+
+        Ok(policy)
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "random"))]
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Serde support for durations written as a number followed by a unit, or as a number of
+/// milliseconds.
+#[cfg(feature = "serde")]
+mod duration {
+    use std::{
+        convert::TryFrom,
+        fmt::{Error as FmtError, Formatter},
+        time::Duration,
+    };
+
+    use serde::{
+        de::{Error, Visitor},
+        Deserializer, Serializer,
+    };
+
+    use super::{format_duration, parse_duration};
+
+    struct DurationVisitor;
+
+    impl Visitor<'_> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+            formatter
+                .write_str("a duration such as \"250ms\" or \"1.5s\", or a number of milliseconds")
+        }
+
+        fn visit_u64<E: Error>(self, millis: u64) -> Result<Duration, E> {
+            Ok(Duration::from_millis(millis))
+        }
+
+        fn visit_i64<E: Error>(self, millis: i64) -> Result<Duration, E> {
+            u64::try_from(millis)
+                .map(Duration::from_millis)
+                .map_err(|_| E::custom("a duration cannot be negative"))
+        }
+
+        fn visit_str<E: Error>(self, text: &str) -> Result<Duration, E> {
+            parse_duration(text).ok_or_else(|| {
+                E::custom(format_args!(
+                    "invalid duration {:?}, expected a number followed by a unit such as \
+                     \"250ms\" or \"1.5s\"",
+                    text
+                ))
+            })
+        }
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_duration(*duration))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+
+    pub(super) mod option {
+        use std::time::Duration;
+
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub(in super::super) fn serialize<S: Serializer>(
+            duration: &Option<Duration>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match duration {
+                Some(duration) => super::serialize(duration, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub(in super::super) fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Duration>, D::Error> {
+            #[derive(Deserialize)]
+            struct Wrapper(#[serde(with = "super")] Duration);
+
+            Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(duration)| duration))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{format_duration, parse_duration, PolicyOptions, RetryPolicy};
+    use crate::delay::Fibonacci;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration(" 1.5s "), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2 m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("10µs"), Some(Duration::from_micros(10)));
+        assert_eq!(parse_duration("7ns"), Some(Duration::from_nanos(7)));
+        assert_eq!(parse_duration("250"), None);
+        assert_eq!(parse_duration("ms"), None);
+        assert_eq!(parse_duration(".ms"), None);
+        assert_eq!(parse_duration("1d"), None);
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::default()), "0s");
+        assert_eq!(format_duration(Duration::from_millis(250)), "250ms");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1500ms");
+        assert_eq!(format_duration(Duration::from_secs(120)), "2m");
+        assert_eq!(format_duration(Duration::from_nanos(7)), "7ns");
+    }
+
+    #[test]
+    fn applies_options() {
+        let options = PolicyOptions {
+            max_tries: Some(4),
+            max_delay: Some(Duration::from_millis(300)),
+            ..PolicyOptions::default()
+        };
+
+        let policy = RetryPolicy::Exponential {
+            base: Duration::from_millis(100),
+            factor: 2.0,
+            options,
+        };
+
+        assert_eq!(
+            policy.delays().collect::<Vec<_>>(),
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(300),
+            ]
+        );
+    }
+
+    #[test]
+    fn delays_are_send() {
+        fn assert_send<T: Send>(_: T) {}
+
+        assert_send(
+            RetryPolicy::NoDelay {
+                options: PolicyOptions::default(),
+            }
+            .delays(),
+        );
+    }
+
+    #[test]
+    fn stops_at_max_total_delay() {
+        let options = PolicyOptions {
+            max_total_delay: Some(Duration::from_millis(50)),
+            ..PolicyOptions::default()
+        };
+
+        let policy = RetryPolicy::Fibonacci {
+            base: Duration::from_millis(10),
+            options,
+        };
+
+        assert_eq!(
+            policy.into_iter().collect::<Vec<_>>(),
+            vec![
+                Duration::from_millis(10),
+                Duration::from_millis(10),
+                Duration::from_millis(20),
+            ]
+        );
+
+        let options = PolicyOptions {
+            max_total_delay: Some(Duration::MAX),
+            ..PolicyOptions::default()
+        };
+        let policy = RetryPolicy::Fixed {
+            delay: Duration::MAX,
+            options,
+        };
+
+        assert_eq!(policy.delays().take(2).collect::<Vec<_>>(), [Duration::MAX]);
+    }
+
+    #[test]
+    fn parses_policy_strings() {
+        let policy: RetryPolicy = "fibonacci(50ms)*5".parse().unwrap();
+        assert_eq!(
+            policy.delays().collect::<Vec<_>>(),
+            Fibonacci::from_millis(50).take(5).collect::<Vec<_>>()
+        );
+
+        let policy: RetryPolicy = " exponential( 100ms , factor=3, max=10s, tries=8 ) "
+            .parse()
+            .unwrap();
+        assert_eq!(
+            policy,
+            RetryPolicy::Exponential {
+                base: Duration::from_millis(100),
+                factor: 3.0,
+                options: PolicyOptions {
+                    max_tries: Some(8),
+                    max_delay: Some(Duration::from_secs(10)),
+                    ..PolicyOptions::default()
+                },
+            }
+        );
+
+        assert_eq!(
+            "no_delay".parse::<RetryPolicy>().unwrap(),
+            RetryPolicy::NoDelay {
+                options: PolicyOptions::default()
+            }
+        );
+    }
+
+    #[test]
+    fn reports_parse_error_positions() {
+        let error = |text: &str| {
+            let error = text.parse::<RetryPolicy>().unwrap_err();
+            (error.position(), error.to_string())
+        };
+
+        assert_eq!(
+            error("linear(1s)"),
+            (0, "unknown strategy `linear` at position 0".to_owned())
+        );
+        assert_eq!(
+            error("fixed(1 sec)"),
+            (8, "expected `,` or `)`, found `s` at position 8".to_owned())
+        );
+        assert_eq!(
+            error("fixed(soon)"),
+            (
+                6,
+                "invalid duration `soon`, expected a number followed by a unit such as `250ms` \
+                 at position 6"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            error("fixed(1s, 2s)"),
+            (
+                10,
+                "`fixed` takes 1 positional argument, found 2 at position 10".to_owned()
+            )
+        );
+        assert_eq!(
+            error("fibonacci(1s, factor=2)"),
+            (
+                14,
+                "unknown option `factor` for `fibonacci` at position 14".to_owned()
+            )
+        );
+        assert_eq!(
+            error("fixed(1s, max=1s, max=2s)"),
+            (
+                18,
+                "option `max` is given more than once at position 18".to_owned()
+            )
+        );
+        assert_eq!(
+            error("fixed(1s"),
+            (
+                8,
+                "expected `,` or `)`, found end of string at position 8".to_owned()
+            )
+        );
+        assert_eq!(
+            error("fixed(1s)*x"),
+            (
+                10,
+                "invalid number of retries `x` at position 10".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_sub_millisecond_delays() {
+        let error = "exponential(500us)".parse::<RetryPolicy>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`exponential` delays must be whole milliseconds, found 500us at position 12"
+        );
+
+        let error = "fibonacci(1500us)".parse::<RetryPolicy>().unwrap_err();
+        assert_eq!(error.position(), 10);
+
+        let policy: RetryPolicy = "fixed(500us)".parse().unwrap();
+        assert_eq!(policy.delays().next(), Some(Duration::from_micros(500)));
+    }
+
+    #[test]
+    fn rejects_invalid_factors() {
+        for (text, found) in [
+            ("exponential(1s, factor=0)", "0"),
+            ("exponential(1s, factor=NaN)", "NaN"),
+            ("exponential(1s, factor=inf)", "inf"),
+        ] {
+            let error = text.parse::<RetryPolicy>().unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "the factor of `exponential` must be a finite number greater than zero, \
+                     found {} at position 23",
+                    found
+                )
+            );
+        }
+
+        let error = "exponential(1s, factor=-1)"
+            .parse::<RetryPolicy>()
+            .unwrap_err();
+        assert_eq!(error.position(), 23);
+    }
+
+    #[test]
+    #[should_panic]
+    fn delays_with_invalid_factor() {
+        let _panic = RetryPolicy::Exponential {
+            base: Duration::from_millis(10),
+            factor: f64::NAN,
+            options: PolicyOptions::default(),
+        }
+        .delays();
+    }
+
+    #[test]
+    fn displays_parseable_policies() {
+        for text in [
+            "no_delay",
+            "no_delay(tries=3)",
+            "fixed(250ms, max_total=1m)",
+            "exponential(100ms, factor=1.5, max=10s, tries=8)",
+            "exponential(100ms, factor=0.5)",
+            "exponential(100ms, factor=0.25)",
+            "fibonacci(50ms, tries=6)",
+        ] {
+            let policy: RetryPolicy = text.parse().unwrap();
+            assert_eq!(policy.to_string(), text);
+            assert_eq!(policy.to_string().parse::<RetryPolicy>().unwrap(), policy);
+        }
+
+        assert_eq!(
+            "fibonacci(50ms)*5"
+                .parse::<RetryPolicy>()
+                .unwrap()
+                .to_string(),
+            "fibonacci(50ms, tries=6)"
+        );
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn parses_random_policies() {
+        let policy: RetryPolicy = "range(10ms, 20ms, jitter=full)".parse().unwrap();
+
+        assert!(policy.options().jitter);
+        assert_eq!(policy.to_string(), "range(10ms, 20ms, jitter=full)");
+        assert_eq!(policy.to_string().parse::<RetryPolicy>().unwrap(), policy);
+
+        let error = "range(20ms, 10ms)".parse::<RetryPolicy>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the minimum of a range, 20ms, is greater than its maximum, 10ms at position 12"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserializes_from_json() {
+        let policy: RetryPolicy = serde_json::from_str(
+            r#"{"strategy": "exponential", "base": "100ms", "max_delay": "1.5s"}"#,
+        )
+        .unwrap();
+
+        let options = PolicyOptions {
+            max_delay: Some(Duration::from_millis(1500)),
+            ..PolicyOptions::default()
+        };
+        assert_eq!(
+            policy,
+            RetryPolicy::Exponential {
+                base: Duration::from_millis(100),
+                factor: 2.0,
+                options,
+            }
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserializes_from_toml() {
+        let policy: RetryPolicy = toml::from_str(
+            r#"
+            strategy = "fixed"
+            delay = 250
+            max_tries = 3
+            max_total_delay = "1m"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            policy.delays().collect::<Vec<_>>(),
+            vec![Duration::from_millis(250); 2]
+        );
+        assert_eq!(
+            policy.options().max_total_delay,
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rejects_invalid_durations() {
+        let error =
+            serde_json::from_str::<RetryPolicy>(r#"{"strategy": "fixed", "delay": "soon"}"#)
+                .unwrap_err();
+
+        assert!(error.to_string().contains("invalid duration \"soon\""));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_durations_readably() {
+        let policy = RetryPolicy::NoDelay {
+            options: PolicyOptions {
+                max_delay: Some(Duration::from_secs(2)),
+                ..PolicyOptions::default()
+            },
+        };
+
+        assert_eq!(
+            serde_json::to_string(&policy).unwrap(),
+            r#"{"strategy":"no_delay","max_delay":"2s"}"#
+        );
+    }
+
+    #[cfg(all(feature = "serde", feature = "random"))]
+    #[test]
+    fn deserializes_range_with_jitter() {
+        let policy: RetryPolicy = serde_json::from_str(
+            r#"{"strategy": "range", "min": "10ms", "max": "20ms", "jitter": true}"#,
+        )
+        .unwrap();
+
+        assert!(policy.options().jitter);
+        assert!(policy
+            .delays()
+            .take(10)
+            .all(|delay| delay <= Duration::from_millis(20)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rejects_sub_millisecond_delays_when_deserializing() {
+        let error =
+            serde_json::from_str::<RetryPolicy>(r#"{"strategy": "exponential", "base": "1.5ms"}"#)
+                .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "`exponential` delays must be whole milliseconds, found 1500us"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rejects_invalid_factor_when_deserializing() {
+        let error = serde_json::from_str::<RetryPolicy>(
+            r#"{"strategy": "exponential", "base": "1s", "factor": -1.0}"#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "the factor of `exponential` must be a finite number greater than zero, found -1"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rejects_unknown_fields() {
+        let error = serde_json::from_str::<RetryPolicy>(
+            r#"{"strategy": "fixed", "delay": "1s", "max_try": 3}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().starts_with("unknown field `max_try`"));
+
+        let error = serde_json::from_str::<RetryPolicy>(
+            r#"{"strategy": "fixed", "delay": "1s", "base": "2s"}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`base` does not apply to the `fixed` strategy"
+        );
+    }
+
+    #[cfg(all(feature = "serde", not(feature = "random")))]
+    #[test]
+    fn rejects_jitter_without_random_feature() {
+        let error = serde_json::from_str::<RetryPolicy>(
+            r#"{"strategy": "fixed", "delay": "1s", "jitter": true}"#,
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().starts_with("unknown field `jitter`"));
+    }
+
+    #[cfg(all(feature = "serde", feature = "random"))]
+    #[test]
+    fn rejects_empty_range() {
+        let error = serde_json::from_str::<RetryPolicy>(
+            r#"{"strategy": "range", "min": "20ms", "max": "10ms"}"#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "the minimum of a range, 20ms, is greater than its maximum, 10ms"
+        );
+    }
+}
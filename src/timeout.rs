@@ -0,0 +1,283 @@
+//! Retries with a time limit on each try.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use retry::delay::Fixed;
+//! use retry::{retry_with_timeout, TimeoutError};
+//!
+//! let result = retry_with_timeout(Fixed::from_millis(10).take(2), Duration::from_millis(10), || {
+//!     std::thread::sleep(Duration::from_secs(1));
+//!     Ok::<_, &str>("too slow")
+//! });
+//!
+//! let error = result.unwrap_err();
+//! assert_eq!(error.error, TimeoutError::Elapsed(Duration::from_millis(10)));
+//! assert_eq!(error.timeouts, 3);
+//! ```
+
+use std::{
+    cell::Cell,
+    error::Error as StdError,
+    fmt::{Display, Error as FmtError, Formatter},
+    future::{poll_fn, Future},
+    panic::resume_unwind,
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    task::Poll,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    asynchronous::retry_async_loop, AsyncSleeper, Error, OperationResult, Retry, RetryAfterOptions,
+    SystemClock,
+};
+
+/// The error from a single try of an operation that is limited by a timeout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimeoutError<E> {
+    /// The try did not finish within the contained timeout.
+    Elapsed(Duration),
+    /// The operation finished within the timeout and returned an error.
+    Operation(E),
+}
+
+impl<E> Display for TimeoutError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            TimeoutError::Elapsed(timeout) => {
+                write!(formatter, "operation timed out after {:?}", timeout)
+            }
+            TimeoutError::Operation(error) => Display::fmt(error, formatter),
+        }
+    }
+}
+
+impl<E> StdError for TimeoutError<E>
+where
+    E: StdError + 'static,
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            TimeoutError::Elapsed(_) => None,
+            TimeoutError::Operation(error) => Some(error),
+        }
+    }
+}
+
+/// Retry the given operation synchronously until it succeeds, or until the given [`Duration`]
+/// iterator ends, giving up on any try that does not finish within the given timeout.
+///
+/// Each try runs on a new thread, so that a try that blocks indefinitely does not block the retry
+/// loop. A try that times out is retried like an [`OperationResult::Retry`] with the error
+/// [`TimeoutError::Elapsed`], and [`Error::timeouts`] reports how many tries timed out. The thread
+/// of a try that timed out is left to finish in the background, and its result is discarded.
+///
+/// To combine a timeout with other limits or hooks, use [`Retry::call_with_timeout`].
+///
+/// # Panics
+///
+/// If the operation panics, the panic is propagated to the caller.
+pub fn retry_with_timeout<I, O, R, E, OR>(
+    iterable: I,
+    timeout: Duration,
+    operation: O,
+) -> Result<R, Error<TimeoutError<E>>>
+where
+    I: IntoIterator<Item = Duration>,
+    O: Fn() -> OR + Send + Sync + 'static,
+    OR: Into<OperationResult<R, E>>,
+    R: Send + 'static,
+    E: Send + 'static,
+{
+    Retry::new(iterable).call_with_timeout(timeout, operation)
+}
+
+/// Make a single try of the operation on a new thread, giving up on it after the timeout and
+/// counting it in `timeouts`.
+pub(crate) fn try_with_timeout<O, R, E, OR>(
+    operation: &Arc<O>,
+    timeout: Duration,
+    timeouts: &Cell<u64>,
+) -> OperationResult<R, TimeoutError<E>>
+where
+    O: Fn() -> OR + Send + Sync + 'static,
+    OR: Into<OperationResult<R, E>>,
+    R: Send + 'static,
+    E: Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let operation = Arc::clone(operation);
+    let handle = thread::spawn(move || {
+        let _ = sender.send(operation().into());
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result.map_err(TimeoutError::Operation),
+        Err(RecvTimeoutError::Timeout) => {
+            timeouts.set(timeouts.get() + 1);
+            OperationResult::Retry(TimeoutError::Elapsed(timeout))
+        }
+        Err(RecvTimeoutError::Disconnected) => match handle.join() {
+            Err(panic) => resume_unwind(panic),
+            Ok(()) => unreachable!("operation thread exited without sending a result"),
+        },
+    }
+}
+
+/// Retry the given asynchronous operation until it succeeds, or until the given [`Duration`]
+/// iterator ends, waiting between tries with the given [`AsyncSleeper`] and giving up on any try
+/// that does not finish within the given timeout.
+///
+/// A try that times out is dropped and retried like an [`OperationResult::Retry`] with the error
+/// [`TimeoutError::Elapsed`], and [`Error::timeouts`] reports how many tries timed out. The
+/// timeout is cooperative: it can only take effect when the operation's future yields.
+pub async fn retry_async_with_timeout<I, S, O, F, R, E, OR>(
+    iterable: I,
+    sleeper: S,
+    timeout: Duration,
+    mut operation: O,
+) -> Result<R, Error<TimeoutError<E>>>
+where
+    I: IntoIterator<Item = Duration>,
+    S: AsyncSleeper,
+    O: FnMut() -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    let timeouts = AtomicU64::new(0);
+
+    let result = retry_async_loop(
+        iterable,
+        SystemClock,
+        |delay| sleeper.sleep(delay),
+        RetryAfterOptions::default(),
+        |_| {
+            let attempt = operation();
+            let timer = sleeper.sleep(timeout);
+            let timeouts = &timeouts;
+
+            async move {
+                let mut attempt = pin!(attempt);
+                let mut timer = pin!(timer);
+
+                poll_fn(|context| {
+                    if let Poll::Ready(result) = attempt.as_mut().poll(context) {
+                        return Poll::Ready(result.into().map_err(TimeoutError::Operation));
+                    }
+
+                    timer.as_mut().poll(context).map(|()| {
+                        timeouts.fetch_add(1, Ordering::Relaxed);
+                        OperationResult::Retry(TimeoutError::Elapsed(timeout))
+                    })
+                })
+                .await
+            }
+        },
+    )
+    .await;
+
+    result.map_err(|error| Error {
+        timeouts: timeouts.load(Ordering::Relaxed),
+        ..error
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::pending,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::{retry_async_with_timeout, retry_with_timeout, TimeoutError};
+    use crate::asynchronous::tests::{block_on, no_sleep};
+    use crate::delay::NoDelay;
+    use crate::{ErrorKind, OperationResult};
+
+    #[test]
+    fn succeeds_within_timeout() {
+        let value =
+            retry_with_timeout(NoDelay, Duration::from_secs(10), || Ok::<_, ()>(1)).unwrap();
+
+        assert_eq!(value, 1);
+    }
+
+    #[test]
+    fn counts_timeouts_and_errors() {
+        let tries = Arc::new(AtomicU64::new(0));
+
+        let res = retry_with_timeout(NoDelay.take(3), Duration::from_millis(20), move || {
+            let current_try = tries.fetch_add(1, Ordering::SeqCst) + 1;
+            if current_try % 2 == 1 {
+                thread::sleep(Duration::from_secs(1));
+            }
+            Err::<(), _>(current_try)
+        });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, TimeoutError::Operation(4));
+        assert_eq!(err.tries, 4);
+        assert_eq!(err.timeouts, 2);
+        assert_eq!(err.kind, ErrorKind::Exhausted);
+    }
+
+    #[test]
+    fn fatal_errors_stop_retrying() {
+        let res = retry_with_timeout(NoDelay, Duration::from_secs(10), || {
+            OperationResult::<(), _>::Err("fatal")
+        });
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, TimeoutError::Operation("fatal"));
+        assert_eq!(err.tries, 1);
+        assert_eq!(err.timeouts, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn propagates_panics() {
+        let _ = retry_with_timeout(NoDelay, Duration::from_secs(10), || -> Result<(), ()> {
+            panic!("boom")
+        });
+    }
+
+    #[test]
+    fn async_timeouts() {
+        let mut current_try = 0;
+
+        let res = block_on(retry_async_with_timeout(
+            NoDelay.take(2),
+            no_sleep,
+            Duration::from_millis(1),
+            || {
+                current_try += 1;
+                async move {
+                    if current_try < 3 {
+                        pending::<()>().await;
+                    }
+                    Err::<(), _>("fail")
+                }
+            },
+        ));
+
+        let err = res.unwrap_err();
+        assert_eq!(err.error, TimeoutError::Operation("fail"));
+        assert_eq!(err.tries, 3);
+        assert_eq!(err.timeouts, 2);
+    }
+}
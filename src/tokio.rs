@@ -0,0 +1,179 @@
+//! Asynchronous retries that wait using the [Tokio](https://tokio.rs) timer. (When the `tokio`
+//! Cargo feature is enabled.)
+//!
+//! Because delays are slept with [`tokio::time::sleep`], retries respect Tokio's virtual time, so
+//! [`tokio::time::pause`] and [`tokio::time::advance`] can be used to test them without waiting.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use retry::delay::Fixed;
+//! # async fn fetch() -> Result<u32, &'static str> { Ok(1) }
+//! async fn fetch_with_retries() -> Result<u32, retry::Error<&'static str>> {
+//!     retry::tokio::retry(Fixed::from_millis(100).take(3), || fetch()).await
+//! }
+//! ```
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use ::tokio::time::sleep;
+
+use crate::{asynchronous::retry_async_loop, Clock, Error, OperationResult, RetryAfterOptions};
+
+/// Reads the time from Tokio's clock, so that elapsed time follows Tokio's virtual time when it is
+/// paused.
+struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        ::tokio::time::Instant::now().into_std()
+    }
+}
+
+/// Retry the given asynchronous operation until it succeeds, or until the given [`Duration`]
+/// iterator ends, waiting between tries with [`tokio::time::sleep`].
+pub async fn retry<I, O, F, R, E, OR>(iterable: I, mut operation: O) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    O: FnMut() -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    retry_with_index(iterable, |_| operation()).await
+}
+
+/// Retry the given asynchronous operation until it succeeds, or until the given [`Duration`]
+/// iterator ends, waiting between tries with [`tokio::time::sleep`] and with each iteration of the
+/// operation receiving the number of the attempt as an argument.
+pub async fn retry_with_index<I, O, F, R, E, OR>(iterable: I, operation: O) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    O: FnMut(u64) -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    retry_async_loop(
+        iterable,
+        TokioClock,
+        sleep,
+        RetryAfterOptions::default(),
+        operation,
+    )
+    .await
+}
+
+/// Retry the given asynchronous operation until it succeeds, or until the given [`Duration`]
+/// iterator ends, waiting between tries with [`tokio::time::sleep`] and handling requested delays
+/// according to the given [`RetryAfterOptions`].
+pub async fn retry_with_options<I, O, F, R, E, OR>(
+    iterable: I,
+    options: RetryAfterOptions,
+    mut operation: O,
+) -> Result<R, Error<E>>
+where
+    I: IntoIterator<Item = Duration>,
+    O: FnMut() -> F,
+    F: Future<Output = OR>,
+    OR: Into<OperationResult<R, E>>,
+{
+    retry_async_loop(iterable, TokioClock, sleep, options, |_| operation()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::ready, time::Duration};
+
+    use ::tokio::time::Instant;
+
+    use super::{retry, retry_with_index, retry_with_options};
+    use crate::delay::{Exponential, Fixed};
+    use crate::{Error, ErrorKind, OperationResult, RetryAfterOptions, RetryPolicy};
+
+    #[::tokio::test(start_paused = true)]
+    async fn sleeps_in_virtual_time() {
+        let start = Instant::now();
+        let mut collection = vec![1, 2, 3].into_iter();
+
+        let value = retry(Fixed::from_millis(60_000), || {
+            ready(match collection.next() {
+                Some(n) if n == 3 => Ok(n),
+                Some(_) => Err("not 3"),
+                None => Err("not 3"),
+            })
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(value, 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(120));
+    }
+
+    #[::tokio::test(start_paused = true)]
+    async fn limits_requested_delay() {
+        let start = Instant::now();
+        let options = RetryAfterOptions::new().max_retry_after(Duration::from_secs(1));
+
+        let res = retry_with_options(Fixed::from_millis(10).take(2), options, || {
+            ready(OperationResult::<(), _>::RetryAfter(
+                "busy",
+                Duration::from_secs(60),
+            ))
+        })
+        .await;
+
+        assert_eq!(res.unwrap_err().tries, 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[::tokio::test(start_paused = true)]
+    async fn fails_after_last_try() {
+        let res = retry(Exponential::from_millis(100).take(2), || {
+            ready(Err::<(), _>("fail"))
+        })
+        .await;
+
+        assert_eq!(
+            res,
+            Err(Error {
+                error: "fail",
+                tries: 3,
+                total_delay: Duration::from_millis(300),
+                operation_time: Duration::default(),
+                elapsed: Duration::from_millis(300),
+                kind: ErrorKind::Exhausted,
+                timeouts: 0,
+                attempts: Vec::new(),
+            })
+        );
+    }
+
+    #[::tokio::test(start_paused = true)]
+    async fn retries_policy_in_spawned_task() {
+        let policy: RetryPolicy = "fixed(10ms, tries=3)".parse().unwrap();
+
+        let res =
+            ::tokio::spawn(async move { retry(&policy, || ready(Err::<(), _>("fail"))).await })
+                .await
+                .unwrap();
+
+        assert_eq!(res.unwrap_err().tries, 3);
+    }
+
+    #[::tokio::test(start_paused = true)]
+    async fn succeeds_with_index() {
+        let value = retry_with_index(Fixed::from_millis(10), |current_try| {
+            ready(if current_try == 3 {
+                Ok(current_try)
+            } else {
+                Err("not 3")
+            })
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(value, 3);
+    }
+}
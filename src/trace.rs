@@ -0,0 +1,186 @@
+//! Instrumentation of synchronous retry loops with the `tracing` crate, when the `tracing` feature
+//! is enabled. Without it, every method here does nothing.
+
+use std::time::Duration;
+
+use crate::{ErrorKind, OperationResult};
+
+/// The span covering a whole retry loop, which is entered until the loop finishes.
+pub(crate) struct LoopSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+impl LoopSpan {
+    /// Open and enter the span for a retry loop.
+    pub(crate) fn enter() -> Self {
+        LoopSpan {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "retry",
+                tries = tracing::field::Empty,
+                total_delay = tracing::field::Empty,
+            )
+            .entered(),
+        }
+    }
+
+    /// Emit an event for a finished try. The delay is the one about to be waited before the next
+    /// try, if any, and the total delay includes it.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn attempt(
+        &self,
+        attempt: u64,
+        outcome: &'static str,
+        delay: Option<Duration>,
+        total_delay: Duration,
+    ) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            attempt,
+            outcome,
+            delay = delay.map(tracing::field::debug),
+            total_delay = ?total_delay,
+            "retry attempt finished",
+        );
+    }
+
+    /// Record the final number of tries and total delay on the span, along with the reason the
+    /// loop gave up, if it did.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn finish(&self, tries: u64, total_delay: Duration, kind: Option<ErrorKind>) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("tries", tries);
+            self.span
+                .record("total_delay", tracing::field::debug(total_delay));
+
+            if let Some(kind) = kind {
+                tracing::debug!(tries, kind = ?kind, "retry gave up");
+            }
+        }
+    }
+}
+
+/// The name of the kind of the given result, as reported in attempt events.
+pub(crate) fn outcome<T, E>(result: &OperationResult<T, E>) -> &'static str {
+    match result {
+        OperationResult::Ok(_) => "ok",
+        OperationResult::Retry(_) | OperationResult::RetryAfter(..) => "retry",
+        OperationResult::Err(_) => "err",
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::{
+        fmt::Debug,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    use crate::delay::Fixed;
+    use crate::{retry, ManualClock, Retry};
+
+    #[derive(Clone, Default)]
+    struct Recorder {
+        lines: Arc<Mutex<Vec<String>>>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    struct Line<'a>(&'a mut String);
+
+    impl Visit for Line<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push_str(&format!(" {}={}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut line = format!("span {}", span.metadata().name());
+            span.record(&mut Line(&mut line));
+            self.lines.lock().unwrap().push(line);
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            let mut line = String::from("record");
+            values.record(&mut Line(&mut line));
+            self.lines.lock().unwrap().push(line);
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut line = String::from("event");
+            event.record(&mut Line(&mut line));
+            self.lines.lock().unwrap().push(line);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn records_attempts() {
+        let recorder = Recorder::default();
+        let clock = ManualClock::new();
+        let mut collection = vec![Err("a"), Ok(1)].into_iter();
+
+        tracing::subscriber::with_default(recorder.clone(), || {
+            Retry::new(Fixed::from_millis(10))
+                .sleeper(&clock)
+                .call(|| collection.next().unwrap())
+                .unwrap();
+        });
+
+        assert_eq!(
+            *recorder.lines.lock().unwrap(),
+            vec![
+                "span retry",
+                "event message=retry attempt finished attempt=1 outcome=retry delay=10ms \
+                 total_delay=10ms",
+                "event message=retry attempt finished attempt=2 outcome=ok total_delay=10ms",
+                "record tries=2",
+                "record total_delay=10ms",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_giving_up() {
+        let recorder = Recorder::default();
+
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let _ = retry(Fixed::from_millis(0).take(0), || Err::<(), _>("fail"));
+        });
+
+        let lines = recorder.lines.lock().unwrap();
+        assert_eq!(
+            lines[1],
+            "event message=retry attempt finished attempt=1 outcome=retry total_delay=0ns"
+        );
+        assert_eq!(
+            lines[4],
+            "event message=retry gave up tries=1 kind=Exhausted"
+        );
+    }
+}